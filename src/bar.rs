@@ -1,15 +1,57 @@
-use crate::{collectors::Collection, config::Config};
+use crate::{
+    channel::CollectionReceiver,
+    collectors::Collection,
+    config::{Align, Config, ErrorDisplay, Flush, Output},
+};
 use anyhow::Result;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver},
+    watch,
+};
 
 pub const NAME_PAGE_UP: &str = "yaib-page-up";
 pub const NAME_PAGE_DOWN: &str = "yaib-page-down";
+pub const NAME_SPACER: &str = "yaib-spacer";
+
+// the width of the spacers between the alignments of i3bar blocks, in spaces
+const SPACER_WIDTH: usize = 16;
+
+// how long after an interval boundary a frame is emitted, so the collections launched on the
+// boundary have arrived.
+const FRAME_SETTLE: std::time::Duration = std::time::Duration::from_millis(50);
+
+// the time until the next wall clock multiple of the interval, e.g. the next whole second.
+pub fn until_boundary(interval: chrono::Duration) -> std::time::Duration {
+    let interval = interval.num_milliseconds().max(1);
+    let now = chrono::Local::now().timestamp_millis();
+
+    std::time::Duration::from_millis((interval - now.rem_euclid(interval)) as u64)
+}
+
+fn frame_deadline(interval: chrono::Duration) -> tokio::time::Instant {
+    tokio::time::Instant::now() + until_boundary(interval) + FRAME_SETTLE
+}
+
+const ERROR_COLOR: &str = "#FF0000";
+
+// a failed block, shown in its place with error_display: block
+fn error_block(name: &str, error: &str) -> Block {
+    Block {
+        full_text: format!("{}: {}", name, error),
+        name: Some(name.to_string()),
+        color: Some(ERROR_COLOR.to_string()),
+        urgent: Some(true),
+        ..Default::default()
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct Bar {
     state: BTreeMap<String, Block>,
+    // when the collector of each block was launched for the collection shown
+    launched: BTreeMap<String, chrono::DateTime<chrono::Local>>,
     internal_state: crate::state::ProtectedState,
 }
 
@@ -17,6 +59,7 @@ impl Bar {
     pub fn new(internal_state: crate::state::ProtectedState) -> Self {
         Self {
             state: BTreeMap::default(),
+            launched: BTreeMap::default(),
             internal_state,
         }
     }
@@ -41,69 +84,327 @@ impl Bar {
         }
     }
 
+    fn write_header(&self, config: &Config, w: &mut impl std::io::Write) -> Result<()> {
+        match config.output() {
+            Output::I3bar => {
+                serde_json::to_writer(
+                    &mut *w,
+                    &Header {
+                        version: 1,
+                        click_events: Some(config.click_events()),
+                        ..Default::default()
+                    },
+                )?;
+                w.write_all("\n[\n".as_bytes())?;
+            }
+            Output::Waybar | Output::Plain | Output::Lemonbar => {}
+        }
+
+        Ok(w.flush()?)
+    }
+
+    fn write_frame(
+        output: Output,
+        separator: &str,
+        w: &mut impl std::io::Write,
+        v: Vec<Block>,
+    ) -> Result<()> {
+        match output {
+            Output::I3bar => serde_json::to_writer(&mut *w, &v)?,
+            Output::Waybar => {
+                for block in v {
+                    serde_json::to_writer(&mut *w, &WaybarBlock::from(block))?;
+                    w.write_all("\n".as_bytes())?;
+                }
+            }
+            // a line of the text of each block, leaving out empty ones
+            Output::Plain => {
+                let text = v
+                    .into_iter()
+                    .map(|block| block.full_text)
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<String>>()
+                    .join(separator);
+                w.write_all(format!("{}\n", text).as_bytes())?;
+            }
+            // a line with the blocks of each alignment joined by the separator, in their colors
+            Output::Lemonbar => {
+                let mut groups: BTreeMap<Align, Vec<String>> = BTreeMap::new();
+                for block in v.into_iter().filter(|block| !block.full_text.is_empty()) {
+                    groups
+                        .entry(block.group)
+                        .or_default()
+                        .push(lemonbar_block(&block));
+                }
+
+                let mut line = String::new();
+                for (align, blocks) in groups {
+                    line += match align {
+                        Align::Left => "%{l}",
+                        Align::Center => "%{c}",
+                        Align::Right => "%{r}",
+                    };
+                    line += &blocks.join(&lemonbar_escape(separator));
+                }
+
+                w.write_all(format!("{}\n", line).as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // With flush: always, each frame is flushed once it is written; otherwise the output flushes
+    // itself at the end of each line.
     pub async fn write_blocks(
         &self,
+        output: Output,
+        separator: String,
+        flush: Flush,
         mut w: impl std::io::Write + Send + 'static,
         mut data: UnboundedReceiver<Vec<Block>>,
     ) -> Result<()> {
+        // i3bar frames are preceded by a comma rather than followed by one, so the array is
+        // well-formed whenever it is closed.
+        let mut first = true;
         while let Some(v) = data.recv().await {
-            serde_json::to_writer(&mut w, &v)?;
-            w.write_all(",\n".as_bytes())?;
-            w.flush()?;
+            if output == Output::I3bar && !std::mem::take(&mut first) {
+                w.write_all(",".as_bytes())?;
+            }
+
+            Self::write_frame(output, &separator, &mut w, v)?;
+            if output == Output::I3bar {
+                w.write_all("\n".as_bytes())?;
+            }
+            if flush == Flush::Always {
+                w.flush()?;
+            }
+            self.internal_state.lock().await.last_frame = Some(chrono::Local::now());
+        }
+
+        if output == Output::I3bar {
+            w.write_all("]\n".as_bytes())?;
+        }
+
+        Ok(w.flush()?)
+    }
+
+    // the blocks of the current page, in the order they are configured. A name used by several
+    // blocks is shown once, where it is first configured.
+    async fn frame(&self, config: &Config) -> Vec<Block> {
+        let mut v = Vec::new();
+        let lock = self.internal_state.lock().await;
+        let page = &config.pages()[lock.page];
+        let errors = match config.error_display() {
+            ErrorDisplay::Block => lock.errors.clone(),
+            ErrorDisplay::Log | ErrorDisplay::Ignore => BTreeMap::new(),
+        };
+        drop(lock);
+        let items = &page.items();
+        let mut names = std::collections::BTreeSet::new();
+        for item in items {
+            if !names.insert(&item.name) {
+                continue;
+            }
+
+            let start = v.len();
+
+            if let Some(error) = errors.get(&item.name) {
+                v.push(error_block(&item.name, error));
+            } else if item.split_lines() {
+                // the blocks of each line of the command, in the order of the lines
+                let prefix = format!("{}.", item.name);
+                let mut lines = self
+                    .state
+                    .range(prefix.clone()..)
+                    .take_while(|(name, _)| name.starts_with(&prefix))
+                    .map(|(_, block)| block.clone())
+                    .collect::<Vec<Block>>();
+                lines.sort_by_key(|block| block.line);
+                v.append(&mut lines);
+            } else if let Some(block) = self.state.get(&item.name) {
+                v.push(block.clone())
+            }
+
+            for block in &mut v[start..] {
+                block.group = item.align.unwrap_or(page.align());
+            }
+        }
+
+        // Blocks are grouped by their alignment, keeping their order within it. i3bar cannot
+        // place blocks, so spacers between the groups stand in.
+        v.sort_by_key(|block| block.group);
+        if config.output() == Output::I3bar {
+            let mut spaced = Vec::new();
+            for block in v {
+                if spaced
+                    .last()
+                    .is_some_and(|last: &Block| last.group != block.group)
+                {
+                    spaced.push(Block {
+                        name: Some(NAME_SPACER.to_string()),
+                        min_width: Some(" ".repeat(SPACER_WIDTH)),
+                        separator: Some(false),
+                        ..Default::default()
+                    });
+                }
+
+                spaced.push(block);
+            }
+
+            v = spaced;
+        }
+
+        v
+    }
+
+    // A split command's first line replaces the blocks of its previous output, so lines which
+    // are gone are no longer shown.
+    async fn insert(&mut self, mut collection: Collection) -> Result<()> {
+        // Collectors run concurrently, so a slow one may finish after a later run of the same
+        // block. What it collected is older than what is shown, and is dropped.
+        let item = collection.item();
+        if self
+            .launched
+            .get(&item.name)
+            .is_some_and(|launched| item.last_updated < *launched)
+        {
+            return Ok(());
+        }
+        self.launched.insert(item.name.clone(), item.last_updated);
+        // a block which collects again is no longer failing
+        self.internal_state.lock().await.errors.remove(&item.name);
+
+        if collection.line() == Some(0) {
+            let prefix = format!("{}.", collection.item().name);
+            self.state.retain(|name, _| !name.starts_with(&prefix));
+        }
+
+        let metrics = collection.metrics();
+        if !metrics.is_empty() {
+            self.internal_state
+                .lock()
+                .await
+                .metrics
+                .insert(collection.name(), metrics);
+        }
+
+        // hidden blocks are left out of the frame until they are shown again
+        if collection.hidden() {
+            self.state.remove(&collection.name());
+            return Ok(());
         }
 
+        // metrics are left raw; only what is shown is smoothed
+        collection.smooth(&mut self.internal_state.lock().await.smoothed);
+        let block = collection.to_block(self.internal_state.clone()).await?;
+        self.state.insert(collection.name(), block);
         Ok(())
     }
 
-    pub async fn emit_status(
+    // the current page, with the page buttons when clicks are handled
+    async fn status_frame(&self, config: &Config) -> Vec<Block> {
+        let mut v = self.frame(config).await;
+
+        if config.click_events() {
+            self.add_page_blocks(&mut v, config.pages().len() - 1).await;
+        }
+
+        v
+    }
+
+    // Renders every collection received into a single frame, without any header, once the
+    // collectors are done and the channel is closed.
+    pub async fn emit_once(
         &mut self,
-        config: Config,
-        mut w: impl std::io::Write + Send + 'static,
-        mut data: UnboundedReceiver<Collection>,
+        config: &Config,
+        mut w: impl std::io::Write,
+        mut data: CollectionReceiver,
     ) -> Result<()> {
-        serde_json::to_writer(
+        while let Some(collection) = data.recv().await {
+            self.insert(collection).await?;
+        }
+
+        Self::write_frame(
+            config.output(),
+            &config.separator(),
             &mut w,
-            &Header {
-                version: 1,
-                click_events: Some(true),
-                ..Default::default()
-            },
+            self.frame(config).await,
         )?;
-        w.write_all("\n[\n".as_bytes())?;
-        w.flush()?;
+        if config.output() == Output::I3bar {
+            w.write_all("\n".as_bytes())?;
+        }
+
+        Ok(w.flush()?)
+    }
+
+    // The output, click events and flush of the configuration at startup are used for the life
+    // of the bar; reloaded configurations only change the pages.
+    pub async fn emit_status(
+        &mut self,
+        mut configs: watch::Receiver<Config>,
+        w: impl std::io::Write + Send + 'static,
+        mut data: CollectionReceiver,
+    ) -> Result<()> {
+        let config = configs.borrow().clone();
+        let flush = config.flush();
+        let mut w: Box<dyn std::io::Write + Send> = match flush {
+            Flush::Always => Box::new(w),
+            Flush::Line => Box::new(std::io::LineWriter::new(w)),
+        };
+        self.write_header(&config, &mut w)?;
 
         let (s, r) = unbounded_channel();
         let obj = self.clone();
-        tokio::spawn(async move { obj.write_blocks(w, r).await.unwrap() });
+        let output = config.output();
+        let separator = config.separator();
+        let writer = tokio::spawn(async move {
+            obj.write_blocks(output, separator, flush, w, r)
+                .await
+                .unwrap()
+        });
 
-        let mut last_send = chrono::Local::now() - config.update_interval();
+        let mut deadline =
+            frame_deadline(config.page_update_interval(self.internal_state.lock().await.page));
         let mut last_sent = Vec::new();
 
-        while let Some(collection) = data.recv().await {
-            let block = collection.to_block(self.internal_state.clone()).await?;
-            self.state.insert(collection.name(), block);
-
-            let now = chrono::Local::now();
-            if last_send + config.update_interval() < now {
-                let mut v = Vec::new();
-                let items = &config.pages()[self.internal_state.lock().await.page].items();
-                for item in items {
-                    if let Some(block) = self.state.get(&item.name) {
-                        v.push(block.clone())
-                    }
+        loop {
+            tokio::select! {
+                collection = data.recv() => {
+                    let Some(collection) = collection else { break };
+                    self.insert(collection).await?;
+                }
+                Ok(()) = configs.changed() => {
+                    // blocks from the old configuration are dropped
+                    self.state.clear();
+                    self.launched.clear();
                 }
+                _ = tokio::time::sleep_until(deadline) => {
+                    let config = configs.borrow().clone();
+                    let v = self.status_frame(&config).await;
 
-                self.add_page_blocks(&mut v, config.pages().len() - 1).await;
+                    if !last_sent.eq(&v) {
+                        s.send(v.clone())?;
+                        last_sent = v;
+                    }
 
-                if !last_sent.eq(&v) {
-                    s.send(v.clone())?;
-                    last_send = now;
-                    last_sent = v;
+                    deadline = frame_deadline(
+                        config.page_update_interval(self.internal_state.lock().await.page),
+                    );
                 }
             }
         }
 
-        Ok(())
+        // the collectors are done: write out what they last sent, then let the writer close
+        // the output.
+        let config = configs.borrow().clone();
+        let v = self.status_frame(&config).await;
+        if !last_sent.eq(&v) {
+            s.send(v)?;
+        }
+
+        drop(s);
+        Ok(writer.await?)
     }
 }
 
@@ -138,21 +439,75 @@ pub struct Block {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub separator: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub separator_block_width: Option<u16>,
+    pub separator_block_width: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub markup: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub background: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub border: Option<String>,
+    // the widths of each side of the border, in pixels
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub border_top: Option<String>,
+    pub border_top: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub border_bottom: Option<String>,
+    pub border_bottom: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub border_left: Option<String>,
+    pub border_left: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub border_right: Option<String>,
+    pub border_right: Option<u32>,
+
+    // not part of the i3bar protocol; used by other outputs
+    #[serde(skip)]
+    pub tooltip: Option<String>,
+    #[serde(skip)]
+    pub percentage: Option<u64>,
+    #[serde(skip)]
+    pub line: Option<usize>,
+    #[serde(skip)]
+    pub group: Align,
+}
+
+// Lemonbar draws "%%" as "%", and any other "%" starts a formatting block or is dropped.
+fn lemonbar_escape(s: &str) -> String {
+    s.replace('%', "%%")
+}
+
+// the text of the block, in its colors
+fn lemonbar_block(block: &Block) -> String {
+    let mut s = lemonbar_escape(&block.full_text);
+
+    if let Some(color) = &block.color {
+        s = format!("%{{F{}}}{}%{{F-}}", color, s);
+    }
+
+    if let Some(background) = &block.background {
+        s = format!("%{{B{}}}{}%{{B-}}", background, s);
+    }
+
+    s
+}
+
+// the JSON format of waybar's custom module
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WaybarBlock {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<u64>,
+}
+
+impl From<Block> for WaybarBlock {
+    fn from(value: Block) -> Self {
+        Self {
+            text: value.full_text,
+            tooltip: value.tooltip,
+            class: value.name,
+            percentage: value.percentage,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -1,80 +1,425 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::{path::PathBuf, sync::Arc};
 use tokio::{
     io::AsyncWriteExt,
+    signal::unix::{signal, SignalKind},
     sync::{
         mpsc::{unbounded_channel, UnboundedReceiver},
-        Mutex,
+        watch, Mutex, Notify,
     },
 };
 use yaib::{
-    bar::Bar,
-    config::{CommandItem, Config},
+    bar::{until_boundary, Bar},
+    channel::collection_channel,
+    collectors::{Snapshot, MODULES},
+    config::{
+        config_paths, find_config, BlockFailed, CommandItem, Config, ErrorDisplay, ModuleType,
+    },
     input::manage_clicks,
+    metrics::manage_metrics,
     state::ProtectedState,
     unix::{manage_unix_socket, SOCKET_PATH},
 };
 
-async fn manage_errors(mut r: UnboundedReceiver<Result<()>>) {
+// SIGUSR1 and SIGUSR2 are taken by refreshes, so pages are flipped with SIGRTMIN+1.
+const NEXT_PAGE_SIGNAL: i32 = 1;
+
+// Failing collectors are backed off rather than stopping the bar, so their errors are only
+// reported, as error_display asks. Errors are kept until the block collects again.
+async fn manage_errors(
+    mut r: UnboundedReceiver<Result<()>>,
+    state: ProtectedState,
+    config: watch::Receiver<Config>,
+) {
     while let Some(error) = r.recv().await {
-        if let Err(error) = error {
-            eprintln!("{}", error);
-            std::process::exit(1);
+        let Err(error) = error else {
+            continue;
+        };
+
+        // kept whatever the display, for status blocks
+        if let Some(failed) = error.downcast_ref::<BlockFailed>() {
+            // the block is already named, so only the causes are shown
+            let message = error
+                .chain()
+                .skip(1)
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": ");
+            state
+                .lock()
+                .await
+                .errors
+                .insert(failed.name.clone(), message);
+        }
+
+        if config.borrow().error_display() != ErrorDisplay::Ignore {
+            log::error!("{:#}", error);
+        }
+    }
+}
+
+// Takes -v, -vv and so on out of the arguments, wherever they appear, and logs to stderr at the
+// level they ask for: warnings and errors, then info such as reloads, then debug such as each
+// collector launched, then everything. RUST_LOG, e.g. RUST_LOG=yaib=debug, overrides them.
+fn init_logging(args: &mut Vec<String>) {
+    let mut verbosity = 0;
+    args.retain(|x| match x.strip_prefix('-') {
+        Some(v) if !v.is_empty() && v.chars().all(|c| c == 'v') => {
+            verbosity += v.len();
+            false
+        }
+        _ => true,
+    });
+
+    let level = match verbosity {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+
+    // stdout is the bar's
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .target(env_logger::Target::Stderr)
+        .init();
+}
+
+// --config wins over YAIB_CONFIG, and either is used even if it does not exist, so the error
+// names it. Otherwise the first of the standard paths that exists is used.
+fn config_file(explicit: Option<&PathBuf>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path.clone());
+    }
+    if let Some(path) = std::env::var_os("YAIB_CONFIG") {
+        return Ok(path.into());
+    }
+
+    let paths = config_paths(
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+        dirs::home_dir(),
+    );
+    find_config(&paths, |x| x.exists())
+}
+
+// takes --config <path> or --config=<path> out of the arguments, wherever it appears
+fn config_arg(args: &mut Vec<String>) -> Result<Option<PathBuf>> {
+    let Some(i) = args
+        .iter()
+        .position(|x| x == "--config" || x.starts_with("--config="))
+    else {
+        return Ok(None);
+    };
+
+    let arg = args.remove(i);
+    if let Some(path) = arg.strip_prefix("--config=") {
+        return Ok(Some(path.into()));
+    }
+    if i < args.len() {
+        return Ok(Some(args.remove(i).into()));
+    }
+
+    Err(anyhow!("--config needs a path"))
+}
+
+// Collects the named block once, printing its text and the value of every placeholder its
+// format may use, so formats can be tried without running the bar.
+async fn test(config: Config, name: &str) -> Result<()> {
+    let Some(mut item) = config.item(name) else {
+        let names = config
+            .pages()
+            .iter()
+            .flat_map(|page| page.items())
+            .map(|item| item.name)
+            .collect::<Vec<_>>();
+        return Err(anyhow!(
+            "Block '{}' could not be found; configured blocks: [{}]",
+            name,
+            names.join(", ")
+        ));
+    };
+
+    let (s_collection, mut r_collection) = collection_channel(config.channel_capacity());
+    let (s_result, mut r_result) = unbounded_channel();
+    let (_, r_commands) = unbounded_channel();
+    let state = ProtectedState::default();
+
+    item.launch_collector(
+        s_collection,
+        s_result,
+        Arc::new(Mutex::new(r_commands)),
+        state.clone(),
+        &Snapshot::default(),
+        config.update_interval(),
+    )
+    .await?;
+    if let Some(Err(e)) = r_result.recv().await {
+        return Err(e);
+    }
+
+    // split commands send a collection per line
+    while let Some(collection) = r_collection.recv().await {
+        let block = collection.to_block(state.clone()).await?;
+        println!("{}: {}", collection.name(), block.full_text);
+        if let Some(short_text) = block.short_text {
+            println!("    short: {}", short_text);
+        }
+
+        for (placeholder, value) in collection.placeholder_values(state.clone()).await {
+            println!("    {} = {}", placeholder, value);
         }
     }
+
+    Ok(())
 }
 
-fn config_file() -> PathBuf {
-    std::env::var("YAIB_CONFIG")
-        .map(|x| x.into())
-        .unwrap_or_else(|_| {
-            dirs::config_local_dir()
-                .map(|x| x.join("yaib"))
-                .unwrap_or(dirs::home_dir().unwrap_or("/".into()))
-                .join("yaib.config.yaml")
-        })
+// collects every block once, prints a single frame and exits.
+async fn once(mut config: Config) -> Result<()> {
+    let (s_collection, r_collection) = collection_channel(config.channel_capacity());
+    let (s_result, mut r_result) = unbounded_channel();
+    let (_, r_commands) = unbounded_channel();
+    let state = ProtectedState::default();
+
+    config
+        .launch_collectors(
+            s_collection,
+            s_result,
+            Arc::new(Mutex::new(r_commands)),
+            state.clone(),
+        )
+        .await?;
+
+    // the channels close once every collector is finished with them
+    Bar::new(state)
+        .emit_once(&config, std::io::stdout(), r_collection)
+        .await?;
+
+    let mut failed = false;
+    while let Some(result) = r_result.recv().await {
+        if let Err(e) = result {
+            log::error!("{:#}", e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mut args = std::env::args();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    init_logging(&mut args);
+    let explicit = config_arg(&mut args)?;
+    let mut args = args.into_iter();
 
-    if let Some(cmd) = args.nth(1) {
+    if let Some(cmd) = args.next() {
         if cmd == "write-block" {
             if let Some(s) = args.next() {
                 let _: CommandItem = serde_json::from_str(&s)?; // just test that it parses
-                let mut stream = tokio::net::UnixStream::connect(SOCKET_PATH).await?;
-                stream.write_all(s.as_bytes()).await?;
+
+                // the socket reads a block per line
+                let line = serde_json::from_str::<serde_json::Value>(&s)?.to_string() + "\n";
+                let path = config_file(explicit.as_ref())
+                    .and_then(Config::load)
+                    .map_or_else(|_| PathBuf::from(SOCKET_PATH), |config| config.socket());
+                let mut stream = tokio::net::UnixStream::connect(path).await?;
+                stream.write_all(line.as_bytes()).await?;
                 drop(stream);
             }
 
             return Ok(());
+        } else if cmd == "validate" {
+            let filename = match args.next() {
+                Some(path) => PathBuf::from(path),
+                None => config_file(explicit.as_ref())?,
+            };
+            let problems = Config::load(filename)?.problems();
+
+            if problems.is_empty() {
+                println!("OK");
+                return Ok(());
+            }
+
+            for problem in problems {
+                println!("{}", problem);
+            }
+
+            std::process::exit(1);
+        } else if cmd == "print-config" {
+            println!("# A yaib configuration to start from; the README describes every setting.");
+            println!(
+                "# Save it as $XDG_CONFIG_HOME/yaib/config.yaml, or pass its path with --config."
+            );
+            print!("{}", serde_yaml::to_string(&Config::sample())?);
+            return Ok(());
+        } else if cmd == "list-modules" {
+            for module in MODULES {
+                if module.aliases.is_empty() {
+                    println!("{}: {}", module.name, module.description);
+                } else {
+                    println!(
+                        "{} (or {}): {}",
+                        module.name,
+                        module.aliases.join(", "),
+                        module.description
+                    );
+                }
+
+                if !module.placeholders.is_empty() {
+                    println!("    placeholders: {}", module.placeholders.join(" "));
+                }
+
+                if let Some(format) = module.typ.default_format() {
+                    println!("    default format: {}", format);
+                }
+            }
+
+            return Ok(());
+        } else if cmd == "test" {
+            let Some(name) = args.next() else {
+                return Err(anyhow!("Usage: yaib test <block name>"));
+            };
+
+            return test(Config::load(config_file(explicit.as_ref())?)?, &name).await;
+        } else if cmd == "--once" {
+            return once(Config::load(config_file(explicit.as_ref())?)?).await;
         }
     }
 
-    let mut config = Config::load(config_file())?;
-    let (s_collection, r_collection) = unbounded_channel();
+    let filename = config_file(explicit.as_ref())?;
+    log::info!("Loading configuration from {}", filename.display());
+    let mut config = Config::load(filename.clone())?;
+    let (_watcher, mut reloads) = config.watch(filename)?;
+    // the capacity is read once, at startup
+    let (s_collection, r_collection) = collection_channel(config.channel_capacity());
     let (s_result, r_result) = unbounded_channel();
     let (s_commands, r_commands) = unbounded_channel();
-    let c = config.clone();
+    let (s_config, r_config) = watch::channel(config.clone());
+    let c = r_config.clone();
     let state = ProtectedState::default();
     let mut bar = Bar::new(state.clone());
 
-    tokio::spawn(async move { manage_unix_socket(s_commands).await });
+    // blocks written to the socket are shown right away
+    let written = Arc::new(Notify::new());
+    let w = written.clone();
+    let socket = config.socket();
     tokio::spawn(async move {
+        if let Err(e) = manage_unix_socket(socket.clone(), s_commands, w).await {
+            log::error!("Could not listen on {}: {:#}", socket.display(), e);
+        }
+    });
+    let status = tokio::spawn(async move {
         bar.emit_status(c, std::io::stdout(), r_collection)
             .await
             .unwrap()
     });
-    tokio::spawn(async move { manage_errors(r_result).await });
-    let c = config.clone();
-    tokio::spawn(async move { manage_clicks(state, c).await });
+    let s = state.clone();
+    let c = r_config.clone();
+    tokio::spawn(async move { manage_errors(r_result, s, c).await });
+    if let Some(port) = config.metrics_port() {
+        let s = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = manage_metrics(port, s).await {
+                log::error!("Could not serve metrics on port {}: {:#}", port, e);
+            }
+        });
+    }
+    if config.click_events() {
+        let c = r_config.clone();
+        let s = state.clone();
+        tokio::spawn(async move { manage_clicks(s, c).await });
+    }
+
     let commands = Arc::new(Mutex::new(r_commands));
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut refresh = signal(SignalKind::user_defined1())?;
+    let mut refresh_types = signal(SignalKind::user_defined2())?;
+    let mut next_page = signal(SignalKind::from_raw(libc::SIGRTMIN() + NEXT_PAGE_SIGNAL))?;
+
+    // when the page last changed, so a page flipped by hand is shown for a whole rotation
+    let mut shown_page = 0;
+    let mut page_shown_at = tokio::time::Instant::now();
 
     loop {
+        // only the most recent of several quick edits matters
+        let mut reloaded = None;
+        while let Ok(c) = reloads.try_recv() {
+            reloaded = Some(c);
+        }
+
+        if let Some(c) = reloaded {
+            config.abort_collectors();
+            config = c;
+
+            let mut lock = state.lock().await;
+            lock.page = lock.page.min(config.pages().len().saturating_sub(1));
+            drop(lock);
+
+            s_config.send(config.clone())?;
+        }
+
+        let mut lock = state.lock().await;
+        if lock.page != shown_page {
+            // flipped by a click, or by a reload with fewer pages
+            shown_page = lock.page;
+            page_shown_at = tokio::time::Instant::now();
+        } else if let Some(rotate) = config.page_rotate().and_then(|x| x.to_std().ok()) {
+            if page_shown_at.elapsed() >= rotate {
+                lock.page = config.next_page(lock.page);
+                shown_page = lock.page;
+                page_shown_at = tokio::time::Instant::now();
+            }
+        }
+        drop(lock);
+
         config
-            .launch_collectors(s_collection.clone(), s_result.clone(), commands.clone())
+            .launch_collectors(
+                s_collection.clone(),
+                s_result.clone(),
+                commands.clone(),
+                state.clone(),
+            )
             .await?;
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        // ticks land on wall clock boundaries, so clocks advance exactly once per second.
+        tokio::select! {
+            _ = tokio::time::sleep(until_boundary(chrono::Duration::milliseconds(100))) => {}
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            // forced collectors are launched right away, on the next pass of the loop
+            _ = refresh.recv() => {
+                log::info!("Refreshing every block");
+                config.force_collectors(None);
+            }
+            _ = refresh_types.recv() => {
+                let types = config.refresh_types();
+                log::info!(
+                    "Refreshing blocks of types: {}",
+                    types.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")
+                );
+                config.force_collectors(Some(&types));
+            }
+            _ = written.notified() => config.force_collectors(Some(&[ModuleType::Dynamic])),
+            _ = next_page.recv() => {
+                let mut lock = state.lock().await;
+                lock.page = config.next_page(lock.page);
+                shown_page = lock.page;
+                page_shown_at = tokio::time::Instant::now();
+            }
+        }
     }
+
+    // no more collectors are launched; once those in flight are done the channel closes, and
+    // the bar writes a final frame and closes its output.
+    drop(s_collection);
+    status.await?;
+
+    // exit instead of returning, as the runtime would otherwise wait on the blocking stdin read
+    // of the click handler.
+    std::process::exit(0);
 }
@@ -0,0 +1,128 @@
+use crate::collectors::Collection;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+// sending fails once the bar has stopped receiving
+#[derive(Debug, Clone, Copy)]
+pub struct Closed;
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The bar is no longer receiving collections")
+    }
+}
+
+impl std::error::Error for Closed {}
+
+#[derive(Debug, Default)]
+struct Queue {
+    collections: VecDeque<Collection>,
+    senders: usize,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    capacity: usize,
+    // wakes the receiver when a collection is queued, or the last sender is gone
+    notify: Notify,
+}
+
+// A channel of collections holding at most its capacity. Only the latest collection of each
+// block matters, so a full channel makes room by dropping the oldest collection of the block
+// being sent, or the oldest of any block if it has none queued, rather than growing or making
+// collectors wait.
+pub fn collection_channel(capacity: usize) -> (CollectionSender, CollectionReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            senders: 1,
+            ..Default::default()
+        }),
+        capacity: capacity.max(1),
+        notify: Notify::new(),
+    });
+
+    (CollectionSender(shared.clone()), CollectionReceiver(shared))
+}
+
+#[derive(Debug)]
+pub struct CollectionSender(Arc<Shared>);
+
+impl CollectionSender {
+    pub fn send(&self, collection: Collection) -> Result<(), Closed> {
+        let mut queue = self.0.queue.lock().unwrap();
+        if queue.closed {
+            return Err(Closed);
+        }
+
+        if queue.collections.len() >= self.0.capacity {
+            let name = collection.name();
+            let oldest = queue
+                .collections
+                .iter()
+                .position(|x| x.name() == name)
+                .unwrap_or(0);
+            queue.collections.remove(oldest);
+        }
+
+        queue.collections.push_back(collection);
+        drop(queue);
+
+        self.0.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for CollectionSender {
+    fn clone(&self) -> Self {
+        self.0.queue.lock().unwrap().senders += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for CollectionSender {
+    fn drop(&mut self) {
+        let mut queue = self.0.queue.lock().unwrap();
+        queue.senders -= 1;
+        if queue.senders == 0 {
+            drop(queue);
+            self.0.notify.notify_one();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CollectionReceiver(Arc<Shared>);
+
+impl CollectionReceiver {
+    // the oldest collection queued, or None once it is empty and every sender is gone
+    pub async fn recv(&mut self) -> Option<Collection> {
+        loop {
+            {
+                let mut queue = self.0.queue.lock().unwrap();
+                if let Some(collection) = queue.collections.pop_front() {
+                    return Some(collection);
+                }
+
+                if queue.senders == 0 {
+                    return None;
+                }
+            }
+
+            // a notification sent since the queue was checked is kept for this wait
+            self.0.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for CollectionReceiver {
+    fn drop(&mut self) {
+        self.0.queue.lock().unwrap().closed = true;
+    }
+}
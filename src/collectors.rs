@@ -1,15 +1,357 @@
 use crate::{
     bar::Block,
-    config::{CommandItem, ConfigItem},
-    formatter::{Format, Rules},
+    channel::CollectionSender,
+    config::{
+        Align, CommandItem, CommandOutput, ConfigItem, ModuleType, Placeholders, WeatherProvider,
+    },
+    formatter::{
+        bar, bytes, escape_markup, pad, scroll, sparkline, truncate, Format, Rules,
+        DEFAULT_BAR_EMPTY, DEFAULT_BAR_FULL, DEFAULT_BAR_WIDTH, DEFAULT_HISTORY,
+        DEFAULT_SCROLL_WIDTH,
+    },
+    state::{
+        CpuSample, DiskIoSample, NetworkSample, ProtectedState, PublicIpSample, WeatherSample,
+    },
 };
 use anyhow::{anyhow, Result};
-use pretty_bytes::converter::convert;
 use std::sync::Arc;
-use tokio::sync::{
-    mpsc::{UnboundedReceiver, UnboundedSender},
-    Mutex,
-};
+use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
+
+// the color of failed commands, without urgency colors
+const DEFAULT_FAILURE_COLOR: &str = "#ff0000";
+
+pub const DEFAULT_ICON_SEPARATOR: &str = " ";
+
+// the value of memory blocks which show swap
+pub const MEMORY_SWAP: &str = "swap";
+
+// #rgb, #rrggbb or #rrggbbaa as red, green, blue and alpha
+fn parse_color(s: &str) -> Option<[u8; 4]> {
+    let digits = s
+        .strip_prefix('#')?
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+
+    match digits.len() {
+        3 => Some([digits[0] * 17, digits[1] * 17, digits[2] * 17, 255]),
+        6 | 8 => {
+            let mut color = [255; 4];
+            for (channel, pair) in color.iter_mut().zip(digits.chunks(2)) {
+                *channel = pair[0] * 16 + pair[1];
+            }
+            Some(color)
+        }
+        _ => None,
+    }
+}
+
+// The colors of a gradient are spread evenly from 0 to 100 percent, and the color between two
+// of them is blended linearly in each of the red, green, blue and alpha channels.
+fn gradient_color(colors: &[String], pct: u64) -> Option<String> {
+    let colors = colors
+        .iter()
+        .map(|color| parse_color(color))
+        .collect::<Option<Vec<[u8; 4]>>>()?;
+    if colors.len() < 2 {
+        return None;
+    }
+
+    let position = pct.min(100) as f64 / 100.0 * (colors.len() - 1) as f64;
+    let index = (position.floor() as usize).min(colors.len() - 2);
+    let (from, to) = (colors[index], colors[index + 1]);
+    let mut blend = [0; 4];
+    for (channel, (from, to)) in blend.iter_mut().zip(from.iter().zip(to.iter())) {
+        *channel =
+            (*from as f64 + (*to as f64 - *from as f64) * (position - index as f64)).round() as u8;
+    }
+
+    Some(if blend[3] == 255 {
+        format!("#{:02x}{:02x}{:02x}", blend[0], blend[1], blend[2])
+    } else {
+        format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            blend[0], blend[1], blend[2], blend[3]
+        )
+    })
+}
+
+// a percentage of the whole; there is nothing of nothing, as with no swap.
+fn percent(part: f64, whole: f64) -> f64 {
+    if whole == 0.0 {
+        0.0
+    } else {
+        part / whole * 100.0
+    }
+}
+
+// what list-modules shows of a module type
+pub struct ModuleInfo {
+    pub typ: ModuleType,
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub placeholders: &'static [&'static str],
+}
+
+// every module type; the aliases must mirror ModuleType, and the placeholders get_formatter.
+pub const MODULES: &[ModuleInfo] = &[
+    ModuleInfo {
+        typ: ModuleType::Static,
+        name: "static",
+        aliases: &[],
+        description: "the text in the value",
+        placeholders: &[],
+    },
+    ModuleInfo {
+        typ: ModuleType::Dynamic,
+        name: "dynamic",
+        aliases: &[],
+        description: "the latest block written to the unix socket under its name",
+        placeholders: &["%value", "%pct"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Command,
+        name: "command",
+        aliases: &[],
+        description: "the output of the command in the value",
+        placeholders: &["%value", "%exit", "%stderr"],
+    },
+    ModuleInfo {
+        typ: ModuleType::CPU,
+        name: "cpu",
+        aliases: &[],
+        description: "CPU usage, of a single core if the value is its index",
+        placeholders: &[
+            "%count",
+            "%usage",
+            "%cores",
+            "%core0",
+            "%core1",
+            "...",
+            "%freq",
+            "%freq_ghz",
+            "%bar",
+            "%sparkline",
+        ],
+    },
+    ModuleInfo {
+        typ: ModuleType::Disk,
+        name: "disk",
+        aliases: &["hdd"],
+        description: "storage of the mount point or device in the value, or of every disk",
+        placeholders: &[
+            "%total",
+            "%usage",
+            "%pct",
+            "%bar",
+            "%inodes_total",
+            "%inodes_used",
+            "%inodes_pct",
+            "%ro",
+            "%degraded",
+        ],
+    },
+    ModuleInfo {
+        typ: ModuleType::Memory,
+        name: "memory",
+        aliases: &["ram"],
+        description: "memory and swap usage, or only swap if the value is swap",
+        placeholders: &[
+            "%total",
+            "%usage",
+            "%available",
+            "%pct_available",
+            "%swap_total",
+            "%swap_usage",
+            "%pct",
+            "%pct_swap",
+            "%bar",
+            "%sparkline",
+        ],
+    },
+    ModuleInfo {
+        typ: ModuleType::Load,
+        name: "load",
+        aliases: &["load_average"],
+        description: "load averages",
+        placeholders: &["%1", "%5", "%15", "%1pct", "%5pct", "%15pct", "%sparkline"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Time,
+        name: "time",
+        aliases: &["clock"],
+        description: "the time, in the IANA timezone in the value; formats use strftime",
+        placeholders: &["%Y", "%m", "%d", "%H", "%M", "%S", "..."],
+    },
+    ModuleInfo {
+        typ: ModuleType::Music,
+        name: "music",
+        aliases: &[],
+        description: "the playing track, from MPRIS or the MPD server in the value",
+        placeholders: &[
+            "%artist",
+            "%title",
+            "%album",
+            "%state",
+            "%position",
+            "%elapsed",
+            "%time",
+            "%length",
+            "%duration",
+            "%progress",
+            "%pct_played",
+            "%bar",
+        ],
+    },
+    ModuleInfo {
+        typ: ModuleType::Battery,
+        name: "battery",
+        aliases: &[],
+        description: "battery charge, of the battery in the value or all of them",
+        placeholders: &["%capacity", "%status", "%time", "%power", "%power_sign"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Network,
+        name: "network",
+        aliases: &["net"],
+        description: "throughput of the interface in the value",
+        placeholders: &["%rx", "%tx", "%iface", "%sparkline", "%peak"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Temperature,
+        name: "temperature",
+        aliases: &["temp"],
+        description: "temperature of the sensor labeled in the value, or the CPU",
+        placeholders: &["%celsius", "%fahrenheit", "%label"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Volume,
+        name: "volume",
+        aliases: &[],
+        description: "volume of the PulseAudio sink in the value, or the default one",
+        placeholders: &["%percent", "%mute"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Brightness,
+        name: "brightness",
+        aliases: &["backlight"],
+        description: "brightness of the backlight in the value, or the first one",
+        placeholders: &["%percent"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Wifi,
+        name: "wifi",
+        aliases: &["wireless"],
+        description: "the wireless connection of the interface in the value",
+        placeholders: &["%ssid", "%signal", "%freq"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Uptime,
+        name: "uptime",
+        aliases: &[],
+        description: "system uptime",
+        placeholders: &["%d", "%h", "%m"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Status,
+        name: "status",
+        aliases: &["self"],
+        description: "yaib's own health: its running collectors, failing blocks and frame lag",
+        placeholders: &["%collectors", "%errors", "%lag"],
+    },
+    ModuleInfo {
+        typ: ModuleType::PublicIp,
+        name: "public_ip",
+        aliases: &["ip"],
+        description: "the public IP address, as seen by the endpoint in the value",
+        placeholders: &["%v4", "%v6"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Mail,
+        name: "mail",
+        aliases: &["imap"],
+        description: "messages in a mailbox of the IMAP server in the value",
+        placeholders: &["%unread", "%total"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Notifications,
+        name: "notifications",
+        aliases: &["dunst"],
+        description: "notifications waiting in dunst, and whether they are paused",
+        placeholders: &["%count", "%paused"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Bluetooth,
+        name: "bluetooth",
+        aliases: &["bt"],
+        description: "the Bluetooth device with the address or name in the value, via BlueZ",
+        placeholders: &["%name", "%battery", "%status"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Keyboard,
+        name: "keyboard",
+        aliases: &["kbd"],
+        description: "the active keyboard layout",
+        placeholders: &["%layout", "%variant"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Processes,
+        name: "processes",
+        aliases: &["procs"],
+        description: "the number of processes, and of those running",
+        placeholders: &["%total", "%running"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Gpu,
+        name: "gpu",
+        aliases: &["nvidia"],
+        description: "usage of the NVIDIA GPU with the index in the value, or the first",
+        placeholders: &[
+            "%usage",
+            "%mem_used",
+            "%mem_total",
+            "%mem_pct",
+            "%temp",
+            "%bar",
+        ],
+    },
+    ModuleInfo {
+        typ: ModuleType::Fan,
+        name: "fan",
+        aliases: &[],
+        description: "the speed of the hwmon fan with the label or index in the value",
+        placeholders: &["%rpm", "%label"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Vpn,
+        name: "vpn",
+        aliases: &["tunnel"],
+        description: "whether the VPN interface in the value is up, and its address",
+        placeholders: &["%status", "%iface", "%ip"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Weather,
+        name: "weather",
+        aliases: &[],
+        description: "the weather at the location in the value, from wttr.in or OpenWeatherMap",
+        placeholders: &["%temp", "%condition", "%location"],
+    },
+    ModuleInfo {
+        typ: ModuleType::Countdown,
+        name: "countdown",
+        aliases: &["timer"],
+        description: "the time left until the time, or after the duration, in the value",
+        placeholders: &["%remaining", "%label"],
+    },
+    ModuleInfo {
+        typ: ModuleType::DiskIo,
+        name: "disk_io",
+        aliases: &["io"],
+        description: "the read and write rates of the block device in the value",
+        placeholders: &["%read", "%write", "%device", "%sparkline", "%peak"],
+    },
+];
 
 #[derive(Debug, Clone)]
 pub struct Collection {
@@ -29,252 +371,3160 @@ impl Collection {
         self.collection_type.clone()
     }
 
-    fn get_formatter(&self) -> Format {
+    pub fn item(&self) -> &crate::config::ConfigItem {
+        &self.item
+    }
+
+    // the line of a split command's output the collection is for
+    pub fn line(&self) -> Option<usize> {
+        match &self.collection_type {
+            CollectionType::Command(command) => command.line,
+            _ => None,
+        }
+    }
+
+    fn bar(&self, pct: f64) -> String {
+        let (full, empty) = self
+            .item
+            .bar_chars
+            .clone()
+            .unwrap_or((DEFAULT_BAR_FULL.to_string(), DEFAULT_BAR_EMPTY.to_string()));
+
+        bar(
+            pct,
+            self.item.bar_width.unwrap_or(DEFAULT_BAR_WIDTH),
+            &full,
+            &empty,
+        )
+    }
+
+    // the history of the block, from the samples of the collections it has shown
+    fn get_formatter(&self, format: Option<String>, history: &[f64]) -> Format<'_> {
+        // blocks with nothing to show use the empty_format instead
+        let (format, builtin) = if self.empty() {
+            match (
+                self.item.empty_format.clone(),
+                self.item.typ.default_empty_format(),
+            ) {
+                (Some(format), _) => (Some(format), false),
+                (None, Some(default)) => (Some(default.to_string()), true),
+                (None, None) => (format, false),
+            }
+        } else {
+            (format, false)
+        };
+        let units = self.item.units.unwrap_or_default();
+        let bytes = |n: f64| {
+            bytes(
+                n,
+                units,
+                self.item.byte_unit.as_deref(),
+                self.item.byte_precision,
+            )
+        };
+        // the number of decimals of numeric placeholders, unless the module's own is overridden
+        let fixed = |x: f64, default: u8| {
+            format!("{:.*}", self.item.precision.unwrap_or(default) as usize, x)
+        };
+        // the history may be kept longer than drawn, for a longer peak_window
+        let drawn = self.item.history.unwrap_or(DEFAULT_HISTORY);
+        let sparkline =
+            |history: &[f64]| sparkline(&history[history.len().saturating_sub(drawn)..]);
+        // the highest rate of the recent updates, per second
+        let peak = || {
+            let window = self.item.peak_window.unwrap_or(drawn);
+            history
+                .iter()
+                .rev()
+                .take(window)
+                .copied()
+                .reduce(f64::max)
+                .map_or_else(|| "n/a".to_string(), |x| format!("{}/s", bytes(x)))
+        };
+        let custom_format = format.is_some();
+        // the built-in formats write their placeholders as %name, whatever the block's setting
+        let builtin = builtin || !custom_format;
+        let format = format.unwrap_or_else(|| {
+            self.item
+                .typ
+                .default_format()
+                .unwrap_or_default()
+                .to_string()
+        });
+
         let pair = match &self.collection_type {
             CollectionType::Static => (self.value.clone().unwrap(), Rules::default()),
-            CollectionType::Command(_) | CollectionType::Dynamic(_) => (
-                if let Some(icon) = &self.item.icon {
-                    format!("{}: {}", icon, self.value.clone().unwrap())
+            // commands with a format show it, with their value, exit code and stderr
+            CollectionType::Command(command) if custom_format => (
+                format.clone(),
+                vec![
+                    ("%value", command.value.clone()),
+                    (
+                        "%exit",
+                        command
+                            .exit
+                            .map_or_else(|| "n/a".to_string(), |x| x.to_string()),
+                    ),
+                    ("%stderr", command.stderr.clone()),
+                ],
+            ),
+            CollectionType::Dynamic(command) => (
+                format.clone(),
+                vec![
+                    ("%value", command.value.clone()),
+                    (
+                        "%pct",
+                        command
+                            .percent
+                            .map_or_else(|| "n/a".to_string(), |x| x.to_string()),
+                    ),
+                ],
+            ),
+            CollectionType::Command(_) => {
+                let value = if self.item.pango() {
+                    escape_markup(&self.value.clone().unwrap())
                 } else {
                     self.value.clone().unwrap()
+                };
+
+                (value, Rules::default())
+            }
+            CollectionType::Time(t, tz) => (
+                if let Some(tz) = tz {
+                    t.with_timezone(tz).format(&format).to_string()
+                } else {
+                    t.format(&format).to_string()
                 },
                 Rules::default(),
             ),
-            CollectionType::Time(t) => (
-                t.format(&self.format.clone().unwrap_or("%m/%d %H:%M".to_string()))
-                    .to_string(),
-                Rules::default(),
-            ),
-            CollectionType::Load(one, five, fifteen) => (
-                self.format.clone().unwrap_or("%1, %5, %15".to_string()),
-                vec![
-                    ("%1", one.to_string()),
-                    ("%5", five.to_string()),
-                    ("%15", fifteen.to_string()),
-                ],
-            ),
-            CollectionType::CPU { count, usage } => (
-                self.format
-                    .clone()
-                    .unwrap_or("cpus: %count, usage: %usage".to_string()),
+            CollectionType::Load(one, five, fifteen) => {
+                let pct = |x: f64| fixed(x / num_cpus::get() as f64 * 100.0, 0);
+
+                (
+                    format.clone(),
+                    vec![
+                        ("%15pct", pct(*fifteen)),
+                        ("%1pct", pct(*one)),
+                        ("%5pct", pct(*five)),
+                        ("%15", fifteen.to_string()),
+                        ("%1", one.to_string()),
+                        ("%5", five.to_string()),
+                        ("%sparkline", sparkline(history)),
+                    ],
+                )
+            }
+            CollectionType::CPU {
+                count,
+                usage,
+                per_core,
+                freq,
+            } => (
+                format.clone(),
                 vec![
                     ("%count", count.to_string()),
-                    ("%usage", format!("{:.2}", usage)),
+                    ("%usage", fixed(*usage, 2)),
+                    (
+                        "%freq_ghz",
+                        freq.map_or_else(|| "n/a".to_string(), |x| fixed(x / 1000.0, 2)),
+                    ),
+                    (
+                        "%freq",
+                        freq.map_or_else(|| "n/a".to_string(), |x| fixed(x, 0)),
+                    ),
+                    ("%bar", self.bar(*usage)),
+                    ("%sparkline", sparkline(history)),
+                    (
+                        "%cores",
+                        per_core
+                            .iter()
+                            .map(|x| fixed(*x, 2))
+                            .collect::<Vec<String>>()
+                            .join(" "),
+                    ),
                 ],
             ),
             CollectionType::Memory {
-                total,
-                usage,
+                total: memory_total,
+                available,
                 swap_total,
                 swap_usage,
+                ..
+            } => {
+                let (total, usage) = self.shown_memory().unwrap_or_default();
+
+                (
+                    format.clone(),
+                    vec![
+                        ("%total", bytes(total as f64)),
+                        ("%usage", bytes(usage as f64)),
+                        ("%available", bytes(*available as f64)),
+                        (
+                            "%pct_available",
+                            fixed(percent(*available as f64, *memory_total as f64), 1),
+                        ),
+                        ("%swap_total", bytes(*swap_total as f64)),
+                        ("%swap_usage", bytes(*swap_usage as f64)),
+                        ("%pct", fixed(percent(usage as f64, total as f64), 1)),
+                        (
+                            "%pct_swap",
+                            fixed(percent(*swap_usage as f64, *swap_total as f64), 1),
+                        ),
+                        ("%bar", self.bar(percent(usage as f64, total as f64))),
+                        ("%sparkline", sparkline(history)),
+                    ],
+                )
+            }
+            CollectionType::Disk {
+                total,
+                usage,
+                inodes_total,
+                inodes_used,
+                read_only,
+                degraded,
+            } => {
+                // filesystems without inodes, like btrfs, report none
+                let inodes = |s: String| {
+                    if *inodes_total == 0 {
+                        "n/a".to_string()
+                    } else {
+                        s
+                    }
+                };
+
+                (
+                    format.clone(),
+                    vec![
+                        ("%total", bytes(*total as f64)),
+                        ("%usage", bytes(*usage as f64)),
+                        ("%pct", fixed(percent(*usage as f64, *total as f64), 1)),
+                        ("%bar", self.bar(percent(*usage as f64, *total as f64))),
+                        ("%inodes_total", inodes(inodes_total.to_string())),
+                        ("%inodes_used", inodes(inodes_used.to_string())),
+                        (
+                            "%inodes_pct",
+                            inodes(fixed(percent(*inodes_used as f64, *inodes_total as f64), 1)),
+                        ),
+                        ("%ro", if *read_only { "yes" } else { "no" }.to_string()),
+                        (
+                            "%degraded",
+                            if *degraded { "yes" } else { "no" }.to_string(),
+                        ),
+                    ],
+                )
+            }
+            CollectionType::Music {
+                artist,
+                title,
+                album,
+                state,
+                elapsed,
+                length,
             } => (
-                self.format
-                    .clone()
-                    .unwrap_or("total: %total, usage: %usage".to_string()),
+                format.clone(),
                 vec![
-                    ("%total", convert(*total as f64)),
-                    ("%usage", convert(*usage as f64)),
-                    ("%swap_total", convert(*swap_total as f64)),
-                    ("%swap_usage", convert(*swap_usage as f64)),
+                    ("%artist", artist.clone()),
+                    ("%title", title.clone()),
+                    ("%album", album.clone()),
+                    ("%state", state.clone()),
                     (
-                        "%pct",
-                        format!("{:.1}", (*usage as f64 / *total as f64) * 100.0),
+                        "%pct_played",
+                        self.music_progress()
+                            .map_or_else(|| "--".to_string(), |x| fixed(x, 0)),
+                    ),
+                    (
+                        "%progress",
+                        self.music_progress()
+                            .map_or_else(|| "--".to_string(), |x| fixed(x, 0)),
                     ),
+                    ("%bar", self.bar(self.music_progress().unwrap_or(0.0))),
+                    ("%time", minutes_seconds(*elapsed)),
+                    ("%elapsed", minutes_seconds(*elapsed)),
+                    ("%position", minutes_seconds(*elapsed)),
                     (
-                        "%pct_swap",
-                        format!("{:.1}", (*swap_usage as f64 / *swap_total as f64) * 100.0),
+                        "%duration",
+                        length.map_or_else(|| "--:--".to_string(), minutes_seconds),
+                    ),
+                    (
+                        "%length",
+                        length.map_or_else(|| "--:--".to_string(), minutes_seconds),
                     ),
                 ],
             ),
-            CollectionType::Disk { total, usage } => (
-                self.format
-                    .clone()
-                    .unwrap_or("total: %total, usage: %usage".to_string()),
+            CollectionType::Battery {
+                capacity,
+                status,
+                time_remaining,
+                power,
+            } => (
+                format.clone(),
                 vec![
-                    ("%total", convert(*total as f64)),
-                    ("%usage", convert(*usage as f64)),
+                    ("%capacity", capacity.to_string()),
+                    ("%status", status.clone()),
                     (
-                        "%pct",
-                        format!("{:.1}", (*usage as f64 / *total as f64) * 100.0),
+                        "%time",
+                        time_remaining.map_or_else(
+                            || "n/a".to_string(),
+                            |t| format!("{}:{:0>2}", t.num_hours(), t.num_minutes() % 60),
+                        ),
+                    ),
+                    (
+                        "%power",
+                        power.map_or_else(|| "n/a".to_string(), |x| fixed(x, 1)),
+                    ),
+                    (
+                        "%power_sign",
+                        match status.as_str() {
+                            "Charging" => "+",
+                            "Discharging" => "-",
+                            _ => "",
+                        }
+                        .to_string(),
                     ),
                 ],
             ),
-            CollectionType::Music {
-                artist,
-                title,
-                pct_played,
-                time_played,
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                iface,
+                ..
             } => (
-                self.format
-                    .clone()
-                    .unwrap_or("music: %artist - %title".to_string()),
+                format.clone(),
                 vec![
-                    ("%artist", artist.clone()),
-                    ("%title", title.clone()),
-                    ("%pct_played", pct_played.to_string()),
+                    ("%rx", format!("{}/s", bytes(*rx_per_sec as f64))),
+                    ("%tx", format!("{}/s", bytes(*tx_per_sec as f64))),
+                    ("%iface", iface.clone()),
+                    ("%sparkline", sparkline(history)),
+                    ("%peak", peak()),
+                ],
+            ),
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                device,
+            } => (
+                format.clone(),
+                vec![
+                    ("%read", format!("{}/s", bytes(*read_per_sec as f64))),
+                    ("%write", format!("{}/s", bytes(*write_per_sec as f64))),
+                    ("%device", device.clone()),
+                    ("%sparkline", sparkline(history)),
+                    ("%peak", peak()),
+                ],
+            ),
+            CollectionType::Temperature { celsius, label } => (
+                format.clone(),
+                vec![
+                    ("%celsius", fixed(*celsius, 1)),
+                    ("%fahrenheit", fixed(celsius * 9.0 / 5.0 + 32.0, 1)),
+                    ("%label", label.clone()),
+                ],
+            ),
+            CollectionType::Volume { percent, muted } => (
+                format.clone(),
+                vec![
+                    ("%percent", percent.to_string()),
                     (
-                        "%time",
-                        format!(
-                            "{}:{:0>2}",
-                            chrono::Duration::seconds(*time_played as i64).num_minutes(),
-                            chrono::Duration::seconds((time_played % 60) as i64).num_seconds()
+                        "%mute",
+                        if *muted {
+                            " (muted)".to_string()
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ],
+            ),
+            CollectionType::Brightness { percent } => {
+                (format.clone(), vec![("%percent", percent.to_string())])
+            }
+            CollectionType::Wifi { ssid, signal, freq } => (
+                format.clone(),
+                vec![
+                    ("%ssid", ssid.clone()),
+                    ("%signal", signal.to_string()),
+                    ("%freq", freq.to_string()),
+                ],
+            ),
+            CollectionType::Uptime { seconds } => (
+                format.clone(),
+                vec![
+                    ("%d", (seconds / 86400).to_string()),
+                    ("%h", ((seconds % 86400) / 3600).to_string()),
+                    ("%m", ((seconds % 3600) / 60).to_string()),
+                ],
+            ),
+            CollectionType::Status {
+                collectors,
+                errors,
+                lag,
+            } => (
+                format.clone(),
+                vec![
+                    ("%collectors", collectors.to_string()),
+                    ("%errors", errors.to_string()),
+                    (
+                        "%lag",
+                        lag.map_or_else(|| "n/a".to_string(), |x| x.num_milliseconds().to_string()),
+                    ),
+                ],
+            ),
+            CollectionType::Countdown { remaining, label } => {
+                // rounded up, so zero is only shown once the countdown is finished
+                let seconds = (remaining.num_milliseconds().max(0) + 999) / 1000;
+                (
+                    format.clone(),
+                    vec![
+                        (
+                            "%remaining",
+                            format!(
+                                "{:02}:{:02}:{:02}",
+                                seconds / 3600,
+                                (seconds % 3600) / 60,
+                                seconds % 60
+                            ),
                         ),
+                        ("%label", label.clone()),
+                    ],
+                )
+            }
+            CollectionType::Mail { unread, total } => (
+                format.clone(),
+                vec![
+                    ("%unread", unread.to_string()),
+                    ("%total", total.to_string()),
+                ],
+            ),
+            CollectionType::Notifications { count, paused, .. } => (
+                format.clone(),
+                vec![
+                    ("%count", count.to_string()),
+                    ("%paused", if *paused { "yes" } else { "no" }.to_string()),
+                ],
+            ),
+            CollectionType::PublicIp { v4, v6 } => (
+                format.clone(),
+                vec![
+                    ("%v4", v4.clone().unwrap_or("n/a".to_string())),
+                    ("%v6", v6.clone().unwrap_or("n/a".to_string())),
+                ],
+            ),
+            CollectionType::Bluetooth {
+                connected,
+                name,
+                battery,
+            } => (
+                format.clone(),
+                vec![
+                    ("%name", name.clone()),
+                    (
+                        "%battery",
+                        battery.map_or_else(|| "n/a".to_string(), |x| x.to_string()),
+                    ),
+                    (
+                        "%status",
+                        if *connected {
+                            "connected".to_string()
+                        } else {
+                            "disconnected".to_string()
+                        },
+                    ),
+                ],
+            ),
+            CollectionType::Keyboard { layout, variant } => (
+                format.clone(),
+                vec![
+                    ("%layout", layout.clone()),
+                    ("%variant", variant.clone().unwrap_or_default()),
+                ],
+            ),
+            CollectionType::Processes { total, running } => (
+                format.clone(),
+                vec![
+                    ("%total", total.to_string()),
+                    ("%running", running.to_string()),
+                ],
+            ),
+            CollectionType::Gpu {
+                usage,
+                mem_used,
+                mem_total,
+                temp,
+            } => (
+                format.clone(),
+                vec![
+                    ("%usage", fixed(*usage, 0)),
+                    ("%mem_used", bytes(*mem_used as f64)),
+                    ("%mem_total", bytes(*mem_total as f64)),
+                    (
+                        "%mem_pct",
+                        fixed(percent(*mem_used as f64, *mem_total as f64), 1),
+                    ),
+                    ("%temp", fixed(*temp, 0)),
+                    ("%bar", self.bar(*usage)),
+                ],
+            ),
+            CollectionType::Fan { rpm, label } => (
+                format.clone(),
+                vec![
+                    (
+                        "%rpm",
+                        rpm.map_or_else(|| "n/a".to_string(), |x| x.to_string()),
+                    ),
+                    ("%label", label.clone()),
+                ],
+            ),
+            CollectionType::Vpn { up, iface, ip } => (
+                format.clone(),
+                vec![
+                    (
+                        "%status",
+                        if *up {
+                            "up".to_string()
+                        } else {
+                            "down".to_string()
+                        },
                     ),
+                    ("%iface", iface.clone()),
+                    ("%ip", ip.clone().unwrap_or("n/a".to_string())),
+                ],
+            ),
+            CollectionType::Weather {
+                temp,
+                condition,
+                location,
+            } => (
+                format.clone(),
+                vec![
+                    ("%temp", fixed(*temp, 0)),
+                    ("%condition", condition.clone()),
+                    ("%location", location.clone()),
                 ],
             ),
         };
-        Format::new(pair.0, pair.1)
+        let mut format = Format::new(pair.0, pair.1)
+            .markup(self.item.pango())
+            .placeholders(if builtin {
+                Placeholders::Percent
+            } else {
+                self.item.placeholders
+            });
+        // cpu blocks have a placeholder for each core
+        if let CollectionType::CPU { per_core, .. } = &self.collection_type {
+            format = format.numbered("%core", per_core.iter().map(|x| fixed(*x, 2)).collect());
+        }
+
+        format
     }
 
-    pub async fn to_block(&self, state: crate::state::ProtectedState) -> Result<Block> {
-        let mut block = Block::default();
+    // the total and used bytes a memory block shows as its own; swap, when its value is swap
+    fn shown_memory(&self) -> Option<(usize, usize)> {
+        match &self.collection_type {
+            CollectionType::Memory {
+                total,
+                usage,
+                swap_total,
+                swap_usage,
+                ..
+            } => Some(if self.item.value.as_deref() == Some(MEMORY_SWAP) {
+                (*swap_total, *swap_usage)
+            } else {
+                (*total, *usage)
+            }),
+            _ => None,
+        }
+    }
 
-        let pct = match &self.collection_type {
-            CollectionType::Static => 0,
-            CollectionType::Command(command) | CollectionType::Dynamic(command) => {
-                command.percent.unwrap_or(0)
-            }
-            CollectionType::CPU { count: _, usage } => usage.floor() as u64,
-            CollectionType::Disk { total, usage } => {
-                ((*usage as f64 / *total as f64) * 100.0).floor() as u64
-            }
-            CollectionType::Load(one, ..) => {
-                ((one / num_cpus::get() as f64) * 100.0).floor() as u64
-            }
-            CollectionType::Memory { total, usage, .. } => {
-                ((*usage as f64 / *total as f64) * 100.0).floor() as u64
+    // the value kept in the history of blocks with a %sparkline placeholder
+    fn sample(&self) -> Option<f64> {
+        match &self.collection_type {
+            CollectionType::CPU { usage, .. } => Some(*usage),
+            CollectionType::Memory { .. } => self
+                .shown_memory()
+                .map(|(total, usage)| percent(usage as f64, total as f64)),
+            CollectionType::Load(one, ..) => Some(*one),
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                ..
+            } => Some((rx_per_sec + tx_per_sec) as f64),
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                ..
+            } => Some((read_per_sec + write_per_sec) as f64),
+            _ => None,
+        }
+    }
+
+    // The jittery values of types which measure rates or usage, which the smooth setting of the
+    // block evens out.
+    fn smoothed_values(&self) -> Option<Vec<f64>> {
+        match &self.collection_type {
+            CollectionType::CPU { usage, .. } | CollectionType::Gpu { usage, .. } => {
+                Some(vec![*usage])
             }
-            CollectionType::Time(..) => 0,
-            CollectionType::Music {
-                artist: _,
-                title: _,
-                pct_played,
-                time_played: _,
-            } => *pct_played as u64,
+            CollectionType::Load(one, ..) => Some(vec![*one]),
+            CollectionType::Temperature { celsius, .. } => Some(vec![*celsius]),
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                ..
+            } => Some(vec![*rx_per_sec as f64, *tx_per_sec as f64]),
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                ..
+            } => Some(vec![*read_per_sec as f64, *write_per_sec as f64]),
+            _ => None,
+        }
+    }
+
+    // Replaces the values of the block with their exponential moving average, weighing the
+    // values it last showed by smooth and the new ones by the rest. The first values, and any
+    // after the block changes type, are shown as they are.
+    pub fn smooth(&mut self, previous: &mut std::collections::BTreeMap<String, Vec<f64>>) {
+        let (Some(smooth), Some(values)) = (self.item.smooth, self.smoothed_values()) else {
+            return;
         };
 
-        let urgency = if let Some(colors) = &self.item.urgency_colors {
-            if let Some(urgency) = self.item.urgency {
-                if pct > urgency.0.into() {
-                    if pct > urgency.1.into() {
-                        if pct > urgency.2.into() {
-                            Some(colors.2.clone())
-                        } else {
-                            Some(colors.1.clone())
-                        }
-                    } else {
-                        Some(colors.0.clone())
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        } else {
-            None
+        let values = match previous.get(&self.name()) {
+            Some(last) if last.len() == values.len() => last
+                .iter()
+                .zip(values)
+                .map(|(last, value)| smooth * last + (1.0 - smooth) * value)
+                .collect(),
+            _ => values,
         };
+        previous.insert(self.name(), values.clone());
 
-        if let Some(urgency) = urgency {
-            block.color = Some(urgency);
+        match &mut self.collection_type {
+            CollectionType::CPU { usage, .. } | CollectionType::Gpu { usage, .. } => {
+                *usage = values[0]
+            }
+            CollectionType::Load(one, ..) => *one = values[0],
+            CollectionType::Temperature { celsius, .. } => *celsius = values[0],
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                ..
+            } => {
+                *rx_per_sec = values[0].round() as u64;
+                *tx_per_sec = values[1].round() as u64;
+            }
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                ..
+            } => {
+                *read_per_sec = values[0].round() as u64;
+                *write_per_sec = values[1].round() as u64;
+            }
+            _ => {}
         }
+    }
 
-        block.name = Some(self.name());
+    // the metrics served to Prometheus, named without their yaib_ prefix
+    pub fn metrics(&self) -> Vec<(&'static str, f64)> {
+        match &self.collection_type {
+            CollectionType::CPU { usage, freq, .. } => {
+                let mut metrics = vec![("cpu_usage_percent", *usage)];
+                if let Some(freq) = freq {
+                    metrics.push(("cpu_frequency_megahertz", *freq));
+                }
+                metrics
+            }
+            CollectionType::Memory {
+                total,
+                usage,
+                available,
+                swap_total,
+                swap_usage,
+            } => vec![
+                ("memory_total_bytes", *total as f64),
+                ("memory_used_bytes", *usage as f64),
+                ("memory_available_bytes", *available as f64),
+                ("memory_used_percent", percent(*usage as f64, *total as f64)),
+                ("swap_total_bytes", *swap_total as f64),
+                ("swap_used_bytes", *swap_usage as f64),
+            ],
+            CollectionType::Disk {
+                total,
+                usage,
+                read_only,
+                degraded,
+                ..
+            } => vec![
+                ("disk_total_bytes", *total as f64),
+                ("disk_used_bytes", *usage as f64),
+                ("disk_used_percent", percent(*usage as f64, *total as f64)),
+                ("disk_read_only", *read_only as u8 as f64),
+                ("disk_degraded", *degraded as u8 as f64),
+            ],
+            CollectionType::Load(one, five, fifteen) => {
+                vec![("load1", *one), ("load5", *five), ("load15", *fifteen)]
+            }
+            CollectionType::Temperature { celsius, .. } => {
+                vec![("temperature_celsius", *celsius)]
+            }
+            CollectionType::Fan { rpm: Some(rpm), .. } => vec![("fan_rpm", *rpm as f64)],
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                ..
+            } => vec![
+                ("network_receive_bytes_per_second", *rx_per_sec as f64),
+                ("network_transmit_bytes_per_second", *tx_per_sec as f64),
+            ],
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                ..
+            } => vec![
+                ("disk_read_bytes_per_second", *read_per_sec as f64),
+                ("disk_written_bytes_per_second", *write_per_sec as f64),
+            ],
+            CollectionType::Battery {
+                capacity, power, ..
+            } => {
+                let mut metrics = vec![("battery_capacity_percent", *capacity as f64)];
+                if let Some(power) = power {
+                    metrics.push(("battery_power_watts", *power));
+                }
+                metrics
+            }
+            CollectionType::Gpu {
+                usage,
+                mem_used,
+                mem_total,
+                temp,
+            } => vec![
+                ("gpu_usage_percent", *usage),
+                ("gpu_memory_used_bytes", *mem_used as f64),
+                ("gpu_memory_total_bytes", *mem_total as f64),
+                ("gpu_temperature_celsius", *temp),
+            ],
+            CollectionType::Status {
+                collectors,
+                errors,
+                lag,
+            } => {
+                let mut metrics = vec![
+                    ("running_collectors", *collectors as f64),
+                    ("failing_blocks", *errors as f64),
+                ];
+                if let Some(lag) = lag {
+                    metrics.push(("frame_lag_seconds", lag.num_milliseconds() as f64 / 1000.0));
+                }
+                metrics
+            }
+            _ => Vec::new(),
+        }
+    }
 
-        if let Some(icon) = &self.item.icon {
-            if state.lock().await.opened.contains(&self.name()) {
-                block.full_text = self.get_formatter().format();
-            } else {
-                block.full_text = icon.clone()
+    // Whether there is nothing to show, as opposed to failing to collect: nothing is playing, a
+    // network interface or tunnel is down, or there is no wireless network.
+    pub fn empty(&self) -> bool {
+        match &self.collection_type {
+            CollectionType::Music { state, .. } => state == MUSIC_STOPPED,
+            CollectionType::Network { up, .. } | CollectionType::Vpn { up, .. } => !up,
+            CollectionType::Wifi { ssid, .. } => ssid.is_empty(),
+            CollectionType::Notifications { running, .. } => !running,
+            _ => false,
+        }
+    }
+
+    // whether the block is left out of the bar, as its hide_when_empty or hide_when_zero ask
+    pub fn hidden(&self) -> bool {
+        (self.item.hide_when_empty.unwrap_or_default() && self.empty())
+            || (self.item.hide_when_zero.unwrap_or_default() && self.zero())
+    }
+
+    // whether the value the block collects is zero, for hide_when_zero. Modules measured in
+    // percent are zero at under 1%, and those which only collect text never are.
+    fn zero(&self) -> bool {
+        match &self.collection_type {
+            CollectionType::Static
+            | CollectionType::Time(..)
+            | CollectionType::PublicIp { .. }
+            | CollectionType::Keyboard { .. } => false,
+            // commands without a percentage are zero when they print 0
+            CollectionType::Command(command) | CollectionType::Dynamic(command) => {
+                command.percent.map_or_else(
+                    || command.value.trim().parse::<f64>().is_ok_and(|x| x == 0.0),
+                    |x| x == 0,
+                )
+            }
+            CollectionType::CPU { .. }
+            | CollectionType::Disk { .. }
+            | CollectionType::Memory { .. }
+            | CollectionType::Load(..)
+            | CollectionType::Music { .. }
+            | CollectionType::Temperature { .. }
+            | CollectionType::Volume { .. }
+            | CollectionType::Brightness { .. }
+            | CollectionType::Gpu { .. } => self.percentage() == 0,
+            CollectionType::Battery { capacity, .. } => *capacity == 0,
+            CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                ..
+            } => *rx_per_sec == 0 && *tx_per_sec == 0,
+            CollectionType::DiskIo {
+                read_per_sec,
+                write_per_sec,
+                ..
+            } => *read_per_sec == 0 && *write_per_sec == 0,
+            CollectionType::Wifi { signal, .. } => *signal == 0,
+            CollectionType::Uptime { seconds } => *seconds == 0,
+            CollectionType::Status { errors, .. } => *errors == 0,
+            CollectionType::Mail { unread, .. } => *unread == 0,
+            CollectionType::Notifications { count, .. } => *count == 0,
+            CollectionType::Bluetooth { connected, .. } => !connected,
+            CollectionType::Processes { running, .. } => *running == 0,
+            CollectionType::Fan { rpm, .. } => rpm.unwrap_or(0) == 0,
+            CollectionType::Vpn { up, .. } => !up,
+            CollectionType::Weather { temp, .. } => temp.round() == 0.0,
+            CollectionType::Countdown { remaining, .. } => *remaining <= chrono::Duration::zero(),
+        }
+    }
+
+    // the percentage of the track played, unless its length is unknown, as with live streams.
+    fn music_progress(&self) -> Option<f64> {
+        match &self.collection_type {
+            CollectionType::Music {
+                elapsed,
+                length: Some(length),
+                ..
+            } if *length > 0 => Some((*elapsed as f64 / *length as f64 * 100.0).min(100.0)),
+            _ => None,
+        }
+    }
+
+    // the percentage used for urgency; higher values are always more urgent.
+    pub fn percentage(&self) -> u64 {
+        match &self.collection_type {
+            CollectionType::Static => 0,
+            CollectionType::Command(command) | CollectionType::Dynamic(command) => {
+                command.percent.unwrap_or(0)
+            }
+            CollectionType::CPU { usage, .. } => usage.floor() as u64,
+            CollectionType::Disk { total, usage, .. } => {
+                percent(*usage as f64, *total as f64).floor() as u64
+            }
+            CollectionType::Load(one, ..) => {
+                ((one / num_cpus::get() as f64) * 100.0).floor() as u64
+            }
+            CollectionType::Memory { .. } => {
+                let (total, usage) = self.shown_memory().unwrap_or_default();
+                percent(usage as f64, total as f64).floor() as u64
+            }
+            CollectionType::Time(..) => 0,
+            CollectionType::Music { .. } => self.music_progress().unwrap_or(0.0).floor() as u64,
+            // batteries become more urgent as they drain
+            CollectionType::Battery { capacity, .. } => 100 - *capacity as u64,
+            CollectionType::Network { .. } => 0,
+            CollectionType::DiskIo { .. } => 0,
+            // degrees celsius are used directly for urgency
+            CollectionType::Temperature { celsius, .. } => celsius.max(0.0).floor() as u64,
+            CollectionType::Volume { percent, .. } => *percent as u64,
+            CollectionType::Brightness { percent } => *percent as u64,
+            // weaker signals are more urgent
+            CollectionType::Wifi { ssid, signal, .. } => {
+                if ssid.is_empty() {
+                    0
+                } else {
+                    100 - *signal as u64
+                }
+            }
+            CollectionType::Uptime { .. } => 0,
+            // each failing block is a point of urgency
+            CollectionType::Status { errors, .. } => (*errors as u64).min(100),
+            CollectionType::PublicIp { .. } => 0,
+            CollectionType::Keyboard { .. } => 0,
+            CollectionType::Processes { .. } => 0,
+            CollectionType::Gpu { usage, .. } => usage.floor() as u64,
+            CollectionType::Fan { .. } => 0,
+            CollectionType::Weather { .. } => 0,
+            // a finished countdown is as urgent as it gets
+            CollectionType::Countdown { remaining, .. } => {
+                if *remaining > chrono::Duration::zero() {
+                    0
+                } else {
+                    100
+                }
+            }
+            // a tunnel which is down is as urgent as it gets
+            CollectionType::Vpn { up, .. } => {
+                if *up {
+                    0
+                } else {
+                    100
+                }
+            }
+            // each unread message is a point of urgency
+            CollectionType::Mail { unread, .. } => (*unread as u64).min(100),
+            // so are waiting notifications
+            CollectionType::Notifications { count, .. } => (*count as u64).min(100),
+            // like batteries, devices become more urgent as they drain
+            CollectionType::Bluetooth { battery, .. } => {
+                battery.map_or(0, |battery| 100 - battery.min(100) as u64)
+            }
+        }
+    }
+
+    // the percentage shown by bars which display one, for modules which have one.
+    pub fn display_percentage(&self) -> Option<u64> {
+        match &self.collection_type {
+            CollectionType::Command(command) | CollectionType::Dynamic(command) => command.percent,
+            CollectionType::Battery { capacity, .. } => Some(*capacity as u64),
+            CollectionType::CPU { .. }
+            | CollectionType::Disk { .. }
+            | CollectionType::Memory { .. }
+            | CollectionType::Load(..)
+            | CollectionType::Music { .. }
+            | CollectionType::Volume { .. }
+            | CollectionType::Brightness { .. }
+            | CollectionType::Gpu { .. } => Some(self.percentage()),
+            CollectionType::Wifi { signal, .. } => Some(*signal as u64),
+            CollectionType::Bluetooth { battery, .. } => battery.map(|x| x as u64),
+            CollectionType::Static
+            | CollectionType::Time(..)
+            | CollectionType::Uptime { .. }
+            | CollectionType::Status { .. }
+            | CollectionType::PublicIp { .. }
+            | CollectionType::Keyboard { .. }
+            | CollectionType::Processes { .. }
+            | CollectionType::Fan { .. }
+            | CollectionType::Vpn { .. }
+            | CollectionType::Weather { .. }
+            | CollectionType::Countdown { .. }
+            | CollectionType::Mail { .. }
+            | CollectionType::Notifications { .. }
+            | CollectionType::Network { .. }
+            | CollectionType::DiskIo { .. }
+            | CollectionType::Temperature { .. } => None,
+        }
+    }
+
+    // The urgency thresholds are the warn, high and critical boundaries. A percentage above a
+    // boundary uses the matching color of urgency_colors; at or below the warn boundary, or
+    // when either field is unset, there is no urgency color.
+    pub fn urgency_color(&self) -> Option<String> {
+        // failed commands are critical
+        if let CollectionType::Command(command) = &self.collection_type {
+            if command.failed() {
+                return Some(
+                    self.item
+                        .urgency_colors
+                        .as_ref()
+                        .map_or(DEFAULT_FAILURE_COLOR.to_string(), |x| x.2.clone()),
+                );
+            }
+        }
+
+        // a gradient replaces the thresholds with a color for every percentage
+        if let Some(gradient) = &self.item.gradient {
+            return gradient_color(gradient, self.percentage());
+        }
+
+        let colors = self.item.urgency_colors.as_ref()?;
+        let (warn, high, critical) = self.item.urgency?;
+        let pct = self.percentage();
+
+        if pct > critical.into() {
+            Some(colors.2.clone())
+        } else if pct > high.into() {
+            Some(colors.1.clone())
+        } else if pct > warn.into() {
+            Some(colors.0.clone())
+        } else {
+            None
+        }
+    }
+
+    // Blocks are urgent once failed, or above the critical boundary of the urgency thresholds,
+    // unless the urgent field of the block says otherwise.
+    pub fn urgent(&self) -> Option<bool> {
+        if self.item.urgent.is_some() {
+            return self.item.urgent;
+        }
+
+        match &self.collection_type {
+            CollectionType::Command(command) if command.failed() => return Some(true),
+            // a degraded array is never expected; a read-only mount may be
+            CollectionType::Disk {
+                read_only,
+                degraded,
+                ..
+            } if *degraded
+                || (*read_only && self.item.urgent_when_read_only.unwrap_or_default()) =>
+            {
+                return Some(true)
+            }
+            _ => {}
+        }
+
+        let (_, _, critical) = self.item.urgency?;
+        (self.percentage() > critical.into()).then_some(true)
+    }
+
+    // batteries may have an icon for each status, falling back to the block's icon.
+    fn icon(&self) -> Option<String> {
+        match &self.collection_type {
+            CollectionType::Battery { status, .. } => self
+                .item
+                .icons
+                .as_ref()
+                .and_then(|icons| icons.get(&status.to_lowercase()))
+                .or(self.item.icon.as_ref())
+                .cloned(),
+            _ => self.item.icon.clone(),
+        }
+    }
+
+    // the prefix and suffix go around text which is already cut and padded; empty text stays
+    // empty, so its block is still hidden.
+    fn decorate(&self, text: String) -> String {
+        if text.is_empty() {
+            return text;
+        }
+
+        format!(
+            "{}{}{}",
+            self.item.prefix.as_deref().unwrap_or_default(),
+            text,
+            self.item.suffix.as_deref().unwrap_or_default()
+        )
+    }
+
+    // the placeholders the format of the block may use, as it writes them, and their values
+    pub async fn placeholder_values(&self, state: ProtectedState) -> Vec<(String, String)> {
+        let history = state
+            .lock()
+            .await
+            .history
+            .get(&self.name())
+            .map(|x| x.iter().copied().collect::<Vec<f64>>())
+            .unwrap_or_default();
+
+        self.get_formatter(self.format.clone(), &history).values()
+    }
+
+    pub async fn to_block(&self, state: crate::state::ProtectedState) -> Result<Block> {
+        // blocks emitted by commands are shown as they are, under the name of their block
+        if let CollectionType::Command(CommandItem {
+            block: Some(block), ..
+        }) = &self.collection_type
+        {
+            return Ok(Block {
+                name: Some(self.name()),
+                separator: block.separator.or(self.item.separator),
+                separator_block_width: block
+                    .separator_block_width
+                    .or(self.item.separator_block_width),
+                urgent: self.item.urgent.or(block.urgent),
+                background: block.background.clone().or(self.item.background.clone()),
+                border: block.border.clone().or(self.item.border.clone()),
+                border_top: block.border_top.or(self.item.border_top),
+                border_bottom: block.border_bottom.or(self.item.border_bottom),
+                border_left: block.border_left.or(self.item.border_left),
+                border_right: block.border_right.or(self.item.border_right),
+                ..block.clone()
+            });
+        }
+
+        let mut block = Block {
+            color: self.urgency_color(),
+            urgent: self.urgent(),
+            percentage: self.display_percentage(),
+            markup: self.item.markup.clone(),
+            separator: self.item.separator,
+            separator_block_width: self.item.separator_block_width,
+            background: self.item.background.clone(),
+            border: self.item.border.clone(),
+            border_top: self.item.border_top,
+            border_bottom: self.item.border_bottom,
+            border_left: self.item.border_left,
+            border_right: self.item.border_right,
+            ..Default::default()
+        };
+
+        block.name = Some(self.name());
+        block.line = self.line();
+        // i3bar keeps the width itself, along with the padding
+        if let Some(width) = self.item.min_width {
+            block.min_width = Some("0".repeat(width));
+            block.align = Some(
+                match self.item.text_align.unwrap_or_default() {
+                    Align::Left => "left",
+                    Align::Center => "center",
+                    Align::Right => "right",
+                }
+                .to_string(),
+            );
+        }
+        let mut lock = state.lock().await;
+        if let Some(sample) = self.sample() {
+            let history = lock.history.entry(self.name()).or_default();
+            history.push_back(sample);
+            let kept = self
+                .item
+                .history
+                .unwrap_or(DEFAULT_HISTORY)
+                .max(self.item.peak_window.unwrap_or(0));
+            while history.len() > kept {
+                history.pop_front();
+            }
+        }
+        let history = lock
+            .history
+            .get(&self.name())
+            .map(|x| x.iter().copied().collect::<Vec<f64>>())
+            .unwrap_or_default();
+        let opened = lock.opened.contains(&self.name());
+
+        // scrolling text moves along once per update, starting over when the text changes
+        let offset = if self.item.scroll.unwrap_or_default() {
+            let text = self.get_formatter(self.format.clone(), &history).format();
+            match lock.scroll.get_mut(&self.name()) {
+                Some(scroll) if scroll.0 == text => {
+                    scroll.1 += 1;
+                    Some(scroll.1)
+                }
+                _ => {
+                    lock.scroll.insert(self.name(), (text, 0));
+                    Some(0)
+                }
             }
         } else {
-            block.full_text = self.get_formatter().format();
+            None
+        };
+        drop(lock);
+
+        // expanded blocks lead with their icon
+        let prefix = self.icon().map_or_else(String::new, |icon| {
+            format!(
+                "{}{}",
+                icon,
+                self.item
+                    .icon_separator
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ICON_SEPARATOR)
+            )
+        });
+        let text = self.get_formatter(self.format.clone(), &history).format();
+        // an empty block without an empty_format is hidden, icon and all
+        if self.empty() && text.is_empty() {
+            return Ok(block);
+        }
+        let full_text = format!("{}{}", prefix, text);
+        let short_text = self.item.short_format.clone().map(|format| {
+            format!(
+                "{}{}",
+                prefix,
+                self.get_formatter(Some(format), &history).format()
+            )
+        });
+
+        match self.icon() {
+            Some(icon) if !opened => {
+                block.full_text = icon;
+                block.tooltip = Some(full_text);
+            }
+            _ => {
+                let full_text = match (offset, self.item.max_len) {
+                    (Some(offset), _) => scroll(
+                        &full_text,
+                        self.item
+                            .scroll_width
+                            .or(self.item.max_len)
+                            .unwrap_or(DEFAULT_SCROLL_WIDTH),
+                        offset,
+                    ),
+                    (None, Some(len)) => truncate(&full_text, len),
+                    (None, None) => full_text,
+                };
+                let full_text = match self.item.min_width {
+                    Some(width) => pad(&full_text, width, self.item.text_align.unwrap_or_default()),
+                    None => full_text,
+                };
+                block.full_text = self.decorate(full_text);
+                block.short_text = short_text.map(|text| self.decorate(text));
+            }
         }
 
         Ok(block)
     }
-}
+}
+
+// every edit to this must mirror a ModuleType
+#[derive(Debug, Clone)]
+pub enum CollectionType {
+    Static,
+    Dynamic(CommandItem),
+    CPU {
+        count: usize,
+        usage: f64,
+        per_core: Vec<f64>,
+        // MHz, where the kernel reports it
+        freq: Option<f64>,
+    },
+    Disk {
+        total: usize,
+        usage: usize,
+        inodes_total: u64,
+        inodes_used: u64,
+        // mounted ro; of every disk, when any is
+        read_only: bool,
+        // on an md array missing a device; of every disk, when any is
+        degraded: bool,
+    },
+    Memory {
+        total: usize,
+        usage: usize,
+        // MemAvailable, which counts the cache the kernel would give up
+        available: usize,
+        swap_total: usize,
+        swap_usage: usize,
+    },
+    Load(f64, f64, f64),
+    Time(chrono::DateTime<chrono::Local>, Option<chrono_tz::Tz>),
+    Music {
+        artist: String,
+        title: String,
+        album: String,
+        state: String,
+        // seconds; live streams have no length
+        elapsed: usize,
+        length: Option<usize>,
+    },
+    Command(CommandItem),
+    Battery {
+        capacity: u8,
+        status: String,
+        time_remaining: Option<chrono::Duration>,
+        // watts flowing in or out, where the driver reports them
+        power: Option<f64>,
+    },
+    Network {
+        rx_per_sec: u64,
+        tx_per_sec: u64,
+        iface: String,
+        up: bool,
+    },
+    DiskIo {
+        read_per_sec: u64,
+        write_per_sec: u64,
+        device: String,
+    },
+    Temperature {
+        celsius: f64,
+        label: String,
+    },
+    Volume {
+        percent: u8,
+        muted: bool,
+    },
+    Brightness {
+        percent: u8,
+    },
+    Wifi {
+        ssid: String,
+        signal: u8,
+        freq: u32,
+    },
+    Uptime {
+        seconds: u64,
+    },
+    Status {
+        collectors: usize,
+        errors: usize,
+        // since the last frame was written, unless none has been
+        lag: Option<chrono::Duration>,
+    },
+    PublicIp {
+        v4: Option<String>,
+        v6: Option<String>,
+    },
+    Mail {
+        unread: u32,
+        total: u32,
+    },
+    Notifications {
+        count: u32,
+        paused: bool,
+        running: bool,
+    },
+    Bluetooth {
+        connected: bool,
+        name: String,
+        battery: Option<u8>,
+    },
+    Keyboard {
+        layout: String,
+        variant: Option<String>,
+    },
+    Processes {
+        total: usize,
+        running: usize,
+    },
+    Gpu {
+        usage: f64,
+        // bytes
+        mem_used: u64,
+        mem_total: u64,
+        temp: f64,
+    },
+    // machines without fans have no speed
+    Fan {
+        rpm: Option<u32>,
+        label: String,
+    },
+    Vpn {
+        up: bool,
+        iface: String,
+        ip: Option<String>,
+    },
+    // celsius
+    Weather {
+        temp: f64,
+        condition: String,
+        location: String,
+    },
+    // never negative; finished countdowns stay at zero
+    Countdown {
+        remaining: chrono::Duration,
+        label: String,
+    },
+}
+
+pub async fn collect_static(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Static,
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+pub async fn collect_time(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    // with no timezone, local time is used
+    let tz = match item.value.as_deref() {
+        None | Some("") => None,
+        Some(value) => Some(
+            value
+                .parse::<chrono_tz::Tz>()
+                .map_err(|_| anyhow!("'{}' is not a valid IANA timezone", value))?,
+        ),
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Time(chrono::Local::now(), tz),
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+// The system as read by the collectors launched together. Each source is read by the first
+// collector which needs it, and the others reuse that read, so blocks of the same update show
+// values from the same instant without reading them once per block.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot(Arc<SnapshotSources>);
+
+#[derive(Debug, Default)]
+struct SnapshotSources {
+    cpu: tokio::sync::OnceCell<CpuSample>,
+    memory: tokio::sync::OnceCell<mprober_lib::memory::Free>,
+    load: tokio::sync::OnceCell<mprober_lib::load_average::LoadAverage>,
+    volumes: tokio::sync::OnceCell<Vec<mprober_lib::volume::Volume>>,
+}
+
+impl Snapshot {
+    pub async fn cpu(&self) -> Result<CpuSample> {
+        Ok(self
+            .0
+            .cpu
+            .get_or_try_init(|| async { read_proc_stat() })
+            .await?
+            .clone())
+    }
+
+    pub async fn memory(&self) -> Result<mprober_lib::memory::Free> {
+        Ok(self
+            .0
+            .memory
+            .get_or_try_init(|| async { Ok::<_, anyhow::Error>(mprober_lib::memory::free()?) })
+            .await?
+            .clone())
+    }
+
+    pub async fn load(&self) -> Result<mprober_lib::load_average::LoadAverage> {
+        Ok(self
+            .0
+            .load
+            .get_or_try_init(|| async {
+                Ok::<_, anyhow::Error>(mprober_lib::load_average::get_load_average()?)
+            })
+            .await?
+            .clone())
+    }
+
+    // volumes may also come from the reads of earlier updates, for the global volume_cache
+    pub async fn volumes(
+        &self,
+        state: &ProtectedState,
+        max_age: chrono::Duration,
+    ) -> Result<Vec<mprober_lib::volume::Volume>> {
+        Ok(self
+            .0
+            .volumes
+            .get_or_try_init(|| cached_volumes(state, max_age))
+            .await?
+            .clone())
+    }
+}
+
+pub async fn collect_load(s: CollectionSender, item: ConfigItem, snapshot: Snapshot) -> Result<()> {
+    let avg = snapshot.load().await?;
+    let clone = item.clone();
+
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Load(avg.one, avg.five, avg.fifteen),
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const PROC_STAT_PATH: &str = "/proc/stat";
+
+fn read_proc_stat() -> Result<CpuSample> {
+    let mut cores = Vec::new();
+
+    for line in std::fs::read_to_string(PROC_STAT_PATH)?.lines() {
+        let mut fields = line.split_whitespace();
+
+        // the aggregate "cpu" line is skipped; only "cpuN" lines are cores
+        match fields.next() {
+            Some(name) if name.starts_with("cpu") && name.len() > 3 => {}
+            _ => continue,
+        }
+
+        // user nice system idle iowait irq softirq steal; guest time is already counted in user.
+        let fields = fields
+            .take(8)
+            .map(|x| x.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<u64>>();
+
+        if fields.len() < 5 {
+            return Err(anyhow!("Malformed cpu entry in {}", PROC_STAT_PATH));
+        }
+
+        cores.push((fields[3] + fields[4], fields.iter().sum()));
+    }
+
+    Ok(CpuSample { cores })
+}
+
+const CPUFREQ_PATH: &str = "/sys/devices/system/cpu";
+const PROC_CPUINFO_PATH: &str = "/proc/cpuinfo";
+
+// The current frequency of each core in MHz, from cpufreq, or /proc/cpuinfo where there is no
+// cpufreq, as in many virtual machines. Cores are in the order of /proc/stat.
+fn read_cpu_freq() -> Vec<f64> {
+    let mut cores = std::fs::read_dir(CPUFREQ_PATH)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let index = name.strip_prefix("cpu")?.parse::<usize>().ok()?;
+            // kHz
+            let freq = read_sysfs_u64(entry.path().join("cpufreq/scaling_cur_freq"))?;
+            Some((index, freq as f64 / 1000.0))
+        })
+        .collect::<Vec<(usize, f64)>>();
+
+    if cores.is_empty() {
+        return std::fs::read_to_string(PROC_CPUINFO_PATH)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == "cpu MHz")
+                    .then(|| value.trim().parse::<f64>().ok())
+                    .flatten()
+            })
+            .collect();
+    }
+
+    cores.sort_by_key(|(index, _)| *index);
+    cores.into_iter().map(|(_, freq)| freq).collect()
+}
+
+pub async fn collect_cpu(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+    snapshot: Snapshot,
+) -> Result<()> {
+    let sample = snapshot.cpu().await?;
+    let last = state
+        .lock()
+        .await
+        .cpu
+        .insert(item.name.clone(), sample.clone())
+        .unwrap_or_default();
+
+    // with no previous sample, usage is measured since boot.
+    let usage = sample
+        .cores
+        .iter()
+        .enumerate()
+        .map(|(i, (idle, total))| {
+            let (last_idle, last_total) = last.cores.get(i).cloned().unwrap_or_default();
+            let total = total.saturating_sub(last_total);
+            if total == 0 {
+                0.0
+            } else {
+                1.0 - (idle.saturating_sub(last_idle) as f64 / total as f64)
+            }
+        })
+        .collect::<Vec<f64>>();
+
+    // count is the number of logical cores, SMT siblings included, matching %cores.
+    let count = usage.len();
+    let per_core = usage.iter().map(|x| x * 100.0).collect::<Vec<f64>>();
+
+    let freqs = read_cpu_freq();
+    let (usage, freq) = if let Some(value) = &item.value {
+        let core = value
+            .parse::<usize>()
+            .map_err(|_| anyhow!("CPU value '{}' must be a core index", value))?;
+
+        let usage = *per_core.get(core).ok_or_else(|| {
+            anyhow!(
+                "CPU core {} could not be found; there are {} cores",
+                core,
+                count
+            )
+        })?;
+        (usage, freqs.get(core).copied())
+    } else {
+        // the mean of the cores, of which /proc/stat may list none
+        let usage = if count == 0 {
+            0.0
+        } else {
+            per_core.iter().fold(0.0, |acc, item| item + acc) / count as f64
+        };
+        let freq = (!freqs.is_empty()).then(|| freqs.iter().sum::<f64>() / freqs.len() as f64);
+        (usage, freq)
+    };
+
+    let clone = item.clone();
+
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::CPU {
+            count,
+            usage,
+            per_core,
+            freq,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+pub async fn collect_memory(
+    s: CollectionSender,
+    item: ConfigItem,
+    snapshot: Snapshot,
+) -> Result<()> {
+    let mem = snapshot.memory().await?;
+    let clone = item.clone();
+
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Memory {
+            total: mem.mem.total,
+            usage: mem.mem.used,
+            available: mem.mem.available,
+            swap_total: mem.swap.total,
+            swap_usage: mem.swap.used,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+fn statvfs(path: &str) -> Result<libc::statvfs> {
+    let c_path = std::ffi::CString::new(path)?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return Err(anyhow!(
+            "Could not read filesystem at '{}': {}",
+            path,
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    Ok(stats)
+}
+
+// The address of the interface, preferring IPv4 to IPv6, if it has one.
+fn interface_address(iface: &str) -> Option<String> {
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return None;
+    }
+
+    let mut v4 = None;
+    let mut v6 = None;
+    let mut cur = addrs;
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+
+        if ifa.ifa_addr.is_null()
+            || unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) }.to_bytes() != iface.as_bytes()
+        {
+            continue;
+        }
+
+        match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                v4 = v4.or(Some(
+                    std::net::Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).to_string(),
+                ));
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                v6 = v6.or(Some(
+                    std::net::Ipv6Addr::from(addr.sin6_addr.s6_addr).to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    unsafe { libc::freeifaddrs(addrs) };
+    v4.or(v6)
+}
+
+const SYS_NET_PATH: &str = "/sys/class/net";
+// the flag of interfaces which are up, from linux/if.h
+const IFF_UP: u32 = 0x1;
+
+// whether the interface exists and has been brought up
+fn interface_up(iface: &str) -> bool {
+    read_sysfs(std::path::Path::new(SYS_NET_PATH).join(iface).join("flags"))
+        .and_then(|x| u32::from_str_radix(x.trim_start_matches("0x"), 16).ok())
+        .is_some_and(|x| x & IFF_UP != 0)
+}
+
+// A tunnel is up when its interface exists and is up; WireGuard and OpenVPN interfaces are
+// removed or brought down when they are not connected.
+pub async fn collect_vpn(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let iface = item.value.clone().unwrap_or_default();
+    let up = interface_up(&iface);
+    let ip = if up { interface_address(&iface) } else { None };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Vpn { up, iface, ip },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+// the total and used inodes of the filesystem mounted at the path
+fn read_inodes(path: &str) -> Result<(u64, u64)> {
+    let stats = statvfs(path)?;
+    let total = stats.f_files as u64;
+    Ok((total, total.saturating_sub(stats.f_ffree as u64)))
+}
+
+const PROC_MOUNTS_PATH: &str = "/proc/mounts";
+
+// filesystems which hold no storage of their own, or which are always full, like snaps.
+pub const VIRTUAL_FILESYSTEMS: &[&str] = &[
+    "tmpfs", "devtmpfs", "proc", "sysfs", "devpts", "cgroup", "cgroup2", "overlay", "squashfs",
+    "ramfs", "efivarfs",
+];
+
+const PROC_MDSTAT_PATH: &str = "/proc/mdstat";
+
+// The md arrays in /proc/mdstat which are missing a device, e.g. md0 from:
+//
+// md0 : active raid1 sdb1[1](F) sda1[0]
+//       1046528 blocks super 1.2 [2/1] [U_]
+fn degraded_arrays(mdstat: &str) -> std::collections::BTreeSet<String> {
+    let mut degraded = std::collections::BTreeSet::new();
+    let mut array = None;
+
+    for line in mdstat.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            array = line.split_once(" : ").map(|(name, _)| name.trim());
+            continue;
+        }
+
+        // the status is the last bracketed field, one U per working device and _ per missing one
+        let Some(array) = array else {
+            continue;
+        };
+        let status = line
+            .split_whitespace()
+            .filter_map(|x| x.strip_prefix('[')?.strip_suffix(']'))
+            .rfind(|x| !x.is_empty() && x.chars().all(|c| c == 'U' || c == '_'));
+        if status.is_some_and(|x| x.contains('_')) {
+            degraded.insert(array.to_string());
+        }
+    }
+
+    degraded
+}
+
+// the md array a device is on, e.g. md0 for md0 or its partition md0p1
+fn md_array(device: &str) -> Option<&str> {
+    let device = device.strip_prefix("/dev/").unwrap_or(device);
+    let digits = device.strip_prefix("md")?;
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    (end > 0).then(|| &device[..2 + end])
+}
+
+fn read_degraded_arrays() -> std::collections::BTreeSet<String> {
+    // without md support, there is no /proc/mdstat and so no arrays
+    std::fs::read_to_string(PROC_MDSTAT_PATH)
+        .map(|x| degraded_arrays(&x))
+        .unwrap_or_default()
+}
+
+// whether the mount point is mounted read-only, from its options in /proc/mounts
+fn read_mount_read_only(point: &str) -> Result<bool> {
+    for line in std::fs::read_to_string(PROC_MOUNTS_PATH)?.lines() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if let [_, mounted, _, options, ..] = fields[..] {
+            if mounted == point || mounted.replace("\\040", " ") == point {
+                return Ok(options.split(',').any(|x| x == "ro"));
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+// The total and used bytes and inodes of every real filesystem, each counted once no matter
+// how many times it is mounted, and whether any is read-only or on a degraded array.
+fn read_all_disks() -> Result<(u64, u64, u64, u64, bool, bool)> {
+    let mut devices = std::collections::BTreeSet::new();
+    let (mut total, mut used, mut inodes_total, mut inodes_used) = (0, 0, 0, 0);
+    let (mut read_only, mut degraded) = (false, false);
+    let arrays = read_degraded_arrays();
+
+    for line in std::fs::read_to_string(PROC_MOUNTS_PATH)?.lines() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        let [device, point, fstype, options, ..] = fields[..] else {
+            continue;
+        };
+
+        if !device.starts_with("/dev/")
+            || VIRTUAL_FILESYSTEMS.contains(&fstype)
+            || !devices.insert(device)
+        {
+            continue;
+        }
+
+        // mount points with spaces are escaped, e.g. /mnt/my\040disk
+        let stats = statvfs(&point.replace("\\040", " "))?;
+        // as with df, blocks are counted in fragments, and the reserved blocks are not used
+        let fragment = stats.f_frsize as u64;
+        total += fragment * stats.f_blocks as u64;
+        used += fragment * (stats.f_blocks as u64).saturating_sub(stats.f_bfree as u64);
+        inodes_total += stats.f_files as u64;
+        inodes_used += (stats.f_files as u64).saturating_sub(stats.f_ffree as u64);
+        read_only |= options.split(',').any(|x| x == "ro");
+        degraded |= md_array(device).is_some_and(|x| arrays.contains(x));
+    }
+
+    Ok((total, used, inodes_total, inodes_used, read_only, degraded))
+}
+
+// the default of the global volume_cache: long enough for every disk block updated on the same
+// tick to share one read of the volumes, and short enough to be read again on the next.
+pub const DEFAULT_VOLUME_CACHE: chrono::Duration = chrono::Duration::milliseconds(500);
+
+// Enumerating the volumes stats every mounted filesystem, so the list is shared by every disk
+// block and only read again once it is older than the cache duration.
+async fn cached_volumes(
+    state: &ProtectedState,
+    max_age: chrono::Duration,
+) -> Result<Vec<mprober_lib::volume::Volume>> {
+    let cache = state.lock().await.volumes.clone();
+    let mut cache = cache.lock().await;
+
+    if let Some((read, volumes)) = &*cache {
+        if max_age
+            .to_std()
+            .is_ok_and(|max_age| read.elapsed() < max_age)
+        {
+            return Ok(volumes.clone());
+        }
+    }
+
+    let volumes = mprober_lib::volume::get_volumes()?;
+    *cache = Some((tokio::time::Instant::now(), volumes.clone()));
+    Ok(volumes)
+}
+
+// a value selects one volume; without one, every real filesystem is added up.
+pub async fn collect_disk(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+    snapshot: Snapshot,
+) -> Result<()> {
+    let (total, usage, inodes_total, inodes_used, read_only, degraded) = if let Some(value) =
+        &item.value
+    {
+        let vols = snapshot.volumes(&state, item.volume_cache).await?;
+
+        // mount points are matched first, then devices, e.g. /dev/nvme0n1p2 or nvme0n1p2
+        let device = value.strip_prefix("/dev/").unwrap_or(value);
+        let target = vols
+            .iter()
+            .find(|vol| vol.points.contains(value))
+            .or_else(|| vols.iter().find(|vol| vol.device == device))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Volume '{}' could not be found; available volumes: [{}]",
+                    value,
+                    vols.iter()
+                        .map(|vol| format!("/dev/{} on {}", vol.device, vol.points.join(", ")))
+                        .collect::<Vec<String>>()
+                        .join("; ")
+                )
+            })?;
+
+        let (inodes_total, inodes_used) = read_inodes(&target.points[0])?;
+        let read_only = read_mount_read_only(&target.points[0])?;
+        let degraded = md_array(&target.device).is_some_and(|x| read_degraded_arrays().contains(x));
+        (
+            target.size,
+            target.used,
+            inodes_total,
+            inodes_used,
+            read_only,
+            degraded,
+        )
+    } else {
+        read_all_disks()?
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Disk {
+            total: total as usize,
+            usage: usage as usize,
+            inodes_total,
+            inodes_used,
+            read_only,
+            degraded,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+pub const MUSIC_PLAYING: &str = "playing";
+pub const MUSIC_PAUSED: &str = "paused";
+pub const MUSIC_STOPPED: &str = "stopped";
+
+// e.g. 3:07
+fn minutes_seconds(seconds: usize) -> String {
+    format!("{}:{:0>2}", seconds / 60, seconds % 60)
+}
+
+pub async fn collect_music(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    // a value is the address of an MPD server; otherwise the active MPRIS player is used.
+    if let Some(address) = &item.value {
+        return collect_mpd(s, item.clone(), address).await;
+    }
+
+    // without a player, nothing is playing
+    let mut collection_type = CollectionType::Music {
+        artist: String::new(),
+        title: String::new(),
+        album: String::new(),
+        state: MUSIC_STOPPED.to_string(),
+        elapsed: 0,
+        length: None,
+    };
+
+    if let Ok(player) = mpris::PlayerFinder::new()?.find_active() {
+        if player.is_running() {
+            if let Ok(meta) = player.get_metadata() {
+                let position = player.get_position().unwrap_or_default();
+                let state = match player.get_playback_status() {
+                    Ok(mpris::PlaybackStatus::Playing) => MUSIC_PLAYING,
+                    Ok(mpris::PlaybackStatus::Paused) => MUSIC_PAUSED,
+                    _ => MUSIC_STOPPED,
+                };
+
+                collection_type = CollectionType::Music {
+                    artist: meta.artists().map_or_else(String::new, |x| x.join(", ")),
+                    title: meta.title().unwrap_or_default().to_string(),
+                    album: meta.album_name().unwrap_or_default().to_string(),
+                    state: state.to_string(),
+                    elapsed: position.as_secs() as usize,
+                    length: meta.length().map(|x| x.as_secs() as usize),
+                };
+            }
+        }
+    }
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type,
+        format: item.format,
+        item: clone,
+        value: None,
+    })?)
+}
+
+// how long to wait on an MPD server before giving up on this update
+const MPD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Asks the MPD server for its status and current song, returning every "key: value" pair of
+// the responses. A connection is made for each update, so a restarted server is picked up on
+// the next one; while the server is down, nothing is reported, as if it were stopped.
+async fn query_mpd(address: &str) -> Result<std::collections::BTreeMap<String, String>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let mut pairs = std::collections::BTreeMap::new();
+    let Ok(stream) = tokio::net::TcpStream::connect(address).await else {
+        return Ok(pairs);
+    };
+    let (r, mut w) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(r).lines();
+
+    // the server greets with "OK MPD <version>"
+    match lines.next_line().await? {
+        Some(line) if line.starts_with("OK MPD") => {}
+        _ => return Err(anyhow!("'{}' is not an MPD server", address)),
+    }
+
+    w.write_all(b"command_list_begin\nstatus\ncurrentsong\ncommand_list_end\nclose\n")
+        .await?;
+
+    while let Some(line) = lines.next_line().await? {
+        if line == "OK" {
+            break;
+        } else if let Some(error) = line.strip_prefix("ACK ") {
+            return Err(anyhow!("MPD at '{}' failed: {}", address, error));
+        } else if let Some((key, value)) = line.split_once(": ") {
+            // the first of repeated tags, such as several artists, is used
+            pairs
+                .entry(key.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+
+    Ok(pairs)
+}
+
+async fn collect_mpd(s: CollectionSender, item: ConfigItem, address: &str) -> Result<()> {
+    let pairs = tokio::time::timeout(MPD_TIMEOUT, query_mpd(address))
+        .await
+        .map_err(|_| anyhow!("MPD at '{}' did not respond", address))??;
+
+    let get = |key: &str| pairs.get(key).cloned().unwrap_or_default();
+    let seconds = |key: &str| pairs.get(key).and_then(|x| x.parse::<f64>().ok());
+
+    let state = match get("state").as_str() {
+        "play" => MUSIC_PLAYING,
+        "pause" => MUSIC_PAUSED,
+        _ => MUSIC_STOPPED,
+    };
+    let elapsed = seconds("elapsed").unwrap_or(0.0);
+    // older servers only report the whole seconds of the song in "Time"
+    let duration = seconds("duration").or(seconds("Time")).filter(|x| *x > 0.0);
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Music {
+            artist: get("Artist"),
+            title: get("Title"),
+            album: get("Album"),
+            state: state.to_string(),
+            elapsed: elapsed as usize,
+            length: duration.map(|x| x as usize),
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+// commands are not run through a shell, just split on whitespace
+pub(crate) fn command_parts(value: &str) -> Vec<String> {
+    value
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+}
+
+const DEFAULT_COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub async fn collect_command(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let clone = item.clone();
+    let timeout = item
+        .timeout
+        .clone()
+        .and_then(|x| x.duration().to_std().ok())
+        .unwrap_or(DEFAULT_COMMAND_TIMEOUT);
+
+    if let Some(value) = item.value {
+        let parts = command_parts(&value);
+        if parts.is_empty() {
+            return Err(anyhow!(
+                "Command block '{}' has an empty command",
+                item.name
+            ));
+        }
+        // a command which runs past its timeout is killed as its output is dropped
+        let output = tokio::time::timeout(
+            timeout,
+            tokio::process::Command::new(parts[0].clone())
+                .args(&parts[1..parts.len()])
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| anyhow!("Command '{}' timed out after {:?}", value, timeout))??;
+        let exit = output.status.code();
+        let stderr = String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .replace('\n', " ");
+
+        // Each line of a split command is a block of its own, named for its leading token
+        // before a tab, or for its index. They are sent in order, starting from the first.
+        if clone.split_lines() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            for (i, line) in stdout.lines().filter(|x| !x.trim().is_empty()).enumerate() {
+                let (key, value) = line
+                    .split_once('\t')
+                    .map_or((i.to_string(), line), |(key, value)| {
+                        (key.to_string(), value)
+                    });
+
+                let command = CommandItem {
+                    name: format!("{}.{}", item.name, key),
+                    value: value.to_string(),
+                    line: Some(i),
+                    exit,
+                    stderr: stderr.clone(),
+                    ..Default::default()
+                };
+
+                s.send(Collection {
+                    name: command.name.clone(),
+                    value: Some(command.value.clone()),
+                    collection_type: CollectionType::Command(command),
+                    item: clone.clone(),
+                    format: item.format.clone(),
+                })?;
+            }
+
+            return Ok(());
+        }
+
+        // the output of a failed command is shown if it has any, but it need not
+        let parsed = match clone.command_output.unwrap_or_default() {
+            CommandOutput::Value => serde_json::from_slice(&output.stdout),
+            CommandOutput::Json => {
+                serde_json::from_slice::<Block>(&output.stdout).map(|block| CommandItem {
+                    name: clone.name.clone(),
+                    value: block.full_text.clone(),
+                    block: Some(block),
+                    ..Default::default()
+                })
+            }
+        };
+
+        let mut command: CommandItem = match parsed {
+            Ok(command) => command,
+            Err(_) if !output.status.success() => CommandItem {
+                name: item.name.clone(),
+                ..Default::default()
+            },
+            Err(e) => return Err(anyhow!("Command '{}' emitted invalid JSON: {}", value, e)),
+        };
+        command.exit = exit;
+        command.stderr = stderr;
+
+        let c = command.clone();
+        s.send(Collection {
+            name: command.name,
+            collection_type: CollectionType::Command(c),
+            item: clone,
+            value: Some(command.value),
+            format: item.format,
+        })?;
+    }
+
+    Ok(())
+}
+
+// Blocks written to the unix socket are kept by name, so each dynamic block shows the latest
+// written under its own name, until another replaces it. Whichever dynamic block runs first
+// takes the blocks written since the last.
+pub async fn collect_dynamic(
+    s: CollectionSender,
+    item: ConfigItem,
+    commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+    state: ProtectedState,
+) -> Result<()> {
+    let mut lock = state.lock().await;
+    while let Ok(command) = commands.lock().await.try_recv() {
+        lock.dynamic.insert(command.name.clone(), command);
+    }
+
+    let Some(command) = lock.dynamic.get(&item.name).cloned() else {
+        return Ok(());
+    };
+    drop(lock);
+
+    s.send(Collection {
+        name: item.name.clone(),
+        value: Some(command.value.clone()),
+        collection_type: CollectionType::Dynamic(command),
+        format: item.format.clone(),
+        item,
+    })?;
+
+    Ok(())
+}
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+fn read_sysfs(path: impl AsRef<std::path::Path>) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|x| x.trim().to_string())
+}
+
+fn read_sysfs_u64(path: impl AsRef<std::path::Path>) -> Option<u64> {
+    read_sysfs(path).and_then(|x| x.parse().ok())
+}
+
+#[derive(Debug, Clone, Default)]
+struct BatteryReading {
+    status: String,
+    now: u64,
+    full: u64,
+    rate: u64,
+    power: Option<f64>,
+}
+
+impl BatteryReading {
+    fn read(path: &std::path::Path) -> Self {
+        let status = read_sysfs(path.join("status")).unwrap_or("Unknown".to_string());
+        let power = Self::read_power(path);
+
+        // some drivers report energy (µWh), others charge (µAh); either yields the same ratios.
+        for (now, full, rate) in [
+            ("energy_now", "energy_full", "power_now"),
+            ("charge_now", "charge_full", "current_now"),
+        ] {
+            if let (Some(now), Some(full)) = (
+                read_sysfs_u64(path.join(now)),
+                read_sysfs_u64(path.join(full)),
+            ) {
+                return Self {
+                    status,
+                    now,
+                    full,
+                    rate: read_sysfs_u64(path.join(rate)).unwrap_or(0),
+                    power,
+                };
+            }
+        }
+
+        Self {
+            status,
+            now: read_sysfs_u64(path.join("capacity")).unwrap_or(0),
+            full: 100,
+            rate: 0,
+            power,
+        }
+    }
+
+    // Watts, from power_now (µW), or current_now (µA) times voltage_now (µV) for drivers which
+    // only report current. Some drivers report negative values while discharging, so the
+    // direction is left to the status.
+    fn read_power(path: &std::path::Path) -> Option<f64> {
+        let read = |name| {
+            read_sysfs(path.join(name))
+                .and_then(|x| x.parse::<i64>().ok())
+                .map(|x| x.unsigned_abs() as f64)
+        };
+
+        read("power_now")
+            .map(|x| x / 1e6)
+            .or_else(|| Some(read("current_now")? * read("voltage_now")? / 1e12))
+    }
+}
+
+fn find_batteries(name: Option<&String>) -> Result<Vec<BatteryReading>> {
+    let mut batteries = Vec::new();
+    let mut names = Vec::new();
+
+    for entry in std::fs::read_dir(POWER_SUPPLY_PATH)? {
+        let path = entry?.path();
+        if read_sysfs(path.join("type")).as_deref() != Some("Battery") {
+            continue;
+        }
+
+        let entry_name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if name.is_none() || name == Some(&entry_name) {
+            batteries.push(BatteryReading::read(&path));
+        }
+
+        names.push(entry_name);
+    }
+
+    if batteries.is_empty() {
+        if let Some(name) = name {
+            return Err(anyhow!(
+                "Battery '{}' could not be found; available batteries: [{}]",
+                name,
+                names.join(", ")
+            ));
+        }
+
+        return Err(anyhow!("No batteries could be found"));
+    }
+
+    Ok(batteries)
+}
+
+pub async fn collect_battery(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let batteries = find_batteries(item.value.as_ref())?;
+
+    let now = batteries.iter().map(|x| x.now).sum::<u64>();
+    let full = batteries.iter().map(|x| x.full).sum::<u64>();
+    let rate = batteries.iter().map(|x| x.rate).sum::<u64>();
+    let power = batteries
+        .iter()
+        .filter_map(|x| x.power)
+        .reduce(|a, b| a + b);
+
+    let status = if batteries.iter().any(|x| x.status == "Charging") {
+        "Charging".to_string()
+    } else if batteries.iter().any(|x| x.status == "Discharging") {
+        "Discharging".to_string()
+    } else {
+        batteries[0].status.clone()
+    };
+
+    // the rate is zero (or absent) when full or idle, so there is nothing to estimate.
+    let remaining = match status.as_str() {
+        "Charging" => Some(full.saturating_sub(now)),
+        "Discharging" => Some(now),
+        _ => None,
+    };
+
+    let time_remaining = remaining.and_then(|remaining| {
+        if rate == 0 {
+            None
+        } else {
+            Some(chrono::Duration::seconds(
+                (remaining as f64 / rate as f64 * 3600.0) as i64,
+            ))
+        }
+    });
+
+    let capacity = if full == 0 {
+        0
+    } else {
+        ((now as f64 / full as f64) * 100.0).round().min(100.0) as u8
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Battery {
+            capacity,
+            status,
+            time_remaining,
+            power,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const NET_DEV_PATH: &str = "/proc/net/dev";
+
+// returns the received and transmitted byte counters for the interface
+fn read_net_dev(iface: &str) -> Result<(u64, u64)> {
+    let contents = std::fs::read_to_string(NET_DEV_PATH)?;
+    let mut names = Vec::new();
+
+    // the first two lines are headers
+    for line in contents.lines().skip(2) {
+        if let Some((name, counters)) = line.split_once(':') {
+            let name = name.trim();
+            if name == iface {
+                let counters = counters
+                    .split_whitespace()
+                    .map(|x| x.parse::<u64>().unwrap_or(0))
+                    .collect::<Vec<u64>>();
+
+                if counters.len() < 9 {
+                    return Err(anyhow!(
+                        "Malformed entry for '{}' in {}",
+                        iface,
+                        NET_DEV_PATH
+                    ));
+                }
+
+                return Ok((counters[0], counters[8]));
+            }
+
+            names.push(name.to_string());
+        }
+    }
+
+    Err(anyhow!(
+        "Interface '{}' could not be found; available interfaces: [{}]",
+        iface,
+        names.join(", ")
+    ))
+}
+
+pub async fn collect_network(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    let clone = item.clone();
+    if let Some(iface) = item.value {
+        let (rx, tx) = read_net_dev(&iface)?;
+        let now = chrono::Local::now();
+
+        // an interface which is up but has lost its link, like an unplugged cable, is down too
+        let unplugged = read_sysfs(
+            std::path::Path::new(SYS_NET_PATH)
+                .join(&iface)
+                .join("carrier"),
+        )
+        .is_some_and(|x| x == "0");
+
+        let sample = NetworkSample { rx, tx, time: now };
+        let last = state.lock().await.network.insert(iface.clone(), sample);
+
+        // the first sample has nothing to compare against, so it reports no traffic.
+        let (rx_per_sec, tx_per_sec) = match last {
+            Some(last) if now > last.time => {
+                let elapsed = (now - last.time).num_milliseconds() as f64 / 1000.0;
+                (
+                    (rx.saturating_sub(last.rx) as f64 / elapsed) as u64,
+                    (tx.saturating_sub(last.tx) as f64 / elapsed) as u64,
+                )
+            }
+            _ => (0, 0),
+        };
+
+        Ok(s.send(Collection {
+            name: item.name,
+            collection_type: CollectionType::Network {
+                rx_per_sec,
+                tx_per_sec,
+                up: interface_up(&iface) && !unplugged,
+                iface: iface.clone(),
+            },
+            value: Some(iface),
+            format: item.format,
+            item: clone,
+        })?)
+    } else {
+        Err(anyhow!(
+            "Value must be provided and must name a network interface"
+        ))
+    }
+}
+
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+// /proc/diskstats counts sectors of 512 bytes, whatever the sector size of the device
+const DISKSTATS_SECTOR_SIZE: u64 = 512;
+
+// the bytes read and written by the device since boot
+fn read_diskstats(device: &str) -> Result<(u64, u64)> {
+    let contents = std::fs::read_to_string(DISKSTATS_PATH)?;
+    let mut names = Vec::new();
+
+    for line in contents.lines() {
+        // the major and minor numbers, the name, then the counters
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            continue;
+        }
+
+        if fields[2] == device {
+            let sector = |n: usize| {
+                fields
+                    .get(n)
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .ok_or_else(|| {
+                        anyhow!("Malformed entry for '{}' in {}", device, DISKSTATS_PATH)
+                    })
+            };
+
+            return Ok((
+                sector(5)? * DISKSTATS_SECTOR_SIZE,
+                sector(9)? * DISKSTATS_SECTOR_SIZE,
+            ));
+        }
+
+        names.push(fields[2].to_string());
+    }
+
+    Err(anyhow!(
+        "Block device '{}' could not be found; available devices: [{}]",
+        device,
+        names.join(", ")
+    ))
+}
+
+pub async fn collect_disk_io(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    let clone = item.clone();
+    let Some(value) = item.value else {
+        return Err(anyhow!(
+            "Value must be provided and must name a block device"
+        ));
+    };
+
+    let device = value.trim_start_matches("/dev/").to_string();
+    let (read, written) = read_diskstats(&device)?;
+    let now = chrono::Local::now();
+
+    let sample = DiskIoSample {
+        read,
+        written,
+        time: now,
+    };
+    let last = state.lock().await.disk_io.insert(device.clone(), sample);
+
+    // like network blocks, the first sample has nothing to compare against.
+    let (read_per_sec, write_per_sec) = match last {
+        Some(last) if now > last.time => {
+            let elapsed = (now - last.time).num_milliseconds() as f64 / 1000.0;
+            (
+                (read.saturating_sub(last.read) as f64 / elapsed) as u64,
+                (written.saturating_sub(last.written) as f64 / elapsed) as u64,
+            )
+        }
+        _ => (0, 0),
+    };
+
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::DiskIo {
+            read_per_sec,
+            write_per_sec,
+            device,
+        },
+        value: Some(value),
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+
+// hwmon drivers which report CPU temperatures, used when no sensor is selected.
+const CPU_HWMON_DRIVERS: &[&str] = &["coretemp", "k10temp", "zenpower", "cpu_thermal"];
+
+#[derive(Debug, Clone)]
+struct Sensor {
+    driver: String,
+    label: String,
+    celsius: f64,
+}
+
+// The inputs of every hwmon chip of the kind, e.g. "temp" or "fan", as the driver, the label
+// and the value. Inputs without a label are labeled by the function.
+fn read_hwmon(
+    kind: &str,
+    label: impl Fn(&str, &str) -> String,
+) -> Result<Vec<(String, String, i64)>> {
+    let mut values = Vec::new();
+
+    let mut chips = std::fs::read_dir(HWMON_PATH)?
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .collect::<Vec<std::path::PathBuf>>();
+    chips.sort();
+
+    for chip in chips {
+        let driver = read_sysfs(chip.join("name")).unwrap_or_default();
+
+        let mut inputs = std::fs::read_dir(&chip)?
+            .filter_map(|x| x.ok())
+            .map(|x| x.file_name().to_string_lossy().to_string())
+            .filter(|x| x.starts_with(kind) && x.ends_with("_input"))
+            .collect::<Vec<String>>();
+        inputs.sort();
+
+        for input in inputs {
+            let prefix = input.trim_end_matches("_input");
+            if let Some(value) = read_sysfs(chip.join(&input)).and_then(|x| x.parse::<i64>().ok()) {
+                values.push((
+                    driver.clone(),
+                    read_sysfs(chip.join(format!("{}_label", prefix)))
+                        .unwrap_or_else(|| label(&driver, prefix)),
+                    value,
+                ));
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+fn read_sensors() -> Result<Vec<Sensor>> {
+    // sensors without a label are named after their driver
+    Ok(read_hwmon("temp", |driver, _| driver.to_string())?
+        .into_iter()
+        .map(|(driver, label, millidegrees)| Sensor {
+            driver,
+            label,
+            celsius: millidegrees as f64 / 1000.0,
+        })
+        .collect())
+}
+
+// Fans are picked by their label, or their index among all fans. Fans without a label are
+// named after their input, e.g. fan1; when none can be found, the speed is unknown.
+pub async fn collect_fan(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let fans = read_hwmon("fan", |_, prefix| prefix.to_string()).unwrap_or_default();
+
+    let fan = match &item.value {
+        Some(value) => fans
+            .iter()
+            .find(|(_, label, _)| label == value)
+            .or_else(|| value.parse::<usize>().ok().and_then(|i| fans.get(i))),
+        None => fans.first(),
+    };
+
+    let (rpm, label) = match fan {
+        Some((_, label, rpm)) => (Some(*rpm as u32), label.clone()),
+        None => (None, item.value.clone().unwrap_or_default()),
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Fan { rpm, label },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+pub async fn collect_temperature(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let sensors = read_sensors()?;
+
+    let sensor = if let Some(value) = &item.value {
+        sensors.iter().find(|x| x.label == *value)
+    } else {
+        sensors
+            .iter()
+            .find(|x| CPU_HWMON_DRIVERS.contains(&x.driver.as_str()))
+            .or(sensors.first())
+    };
+
+    if let Some(sensor) = sensor {
+        let clone = item.clone();
+        Ok(s.send(Collection {
+            name: item.name,
+            collection_type: CollectionType::Temperature {
+                celsius: sensor.celsius,
+                label: sensor.label.clone(),
+            },
+            value: item.value,
+            format: item.format,
+            item: clone,
+        })?)
+    } else {
+        Err(anyhow!(
+            "Temperature sensor '{}' could not be found; available sensors: [{}]",
+            item.value.unwrap_or_default(),
+            sensors
+                .iter()
+                .map(|x| x.label.clone())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+}
+
+async fn pactl(args: &[&str]) -> Result<Vec<u8>> {
+    let output = tokio::process::Command::new("pactl")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow!("Could not run pactl; is PulseAudio installed? ({})", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "pactl failed; is PulseAudio running? ({})",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+pub async fn collect_volume(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let sink = if let Some(value) = &item.value {
+        value.clone()
+    } else {
+        String::from_utf8_lossy(&pactl(&["get-default-sink"]).await?)
+            .trim()
+            .to_string()
+    };
+
+    let sinks: Vec<serde_json::Value> =
+        serde_json::from_slice(&pactl(&["--format=json", "list", "sinks"]).await?)?;
+
+    let found = sinks
+        .iter()
+        .find(|x| x["name"].as_str() == Some(&sink))
+        .ok_or_else(|| {
+            anyhow!(
+                "Sink '{}' could not be found; available sinks: [{}]",
+                sink,
+                sinks
+                    .iter()
+                    .filter_map(|x| x["name"].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )
+        })?;
+
+    // each channel has its own volume, e.g. "value_percent": "65%"; the bar shows the average.
+    let channels = found["volume"]
+        .as_object()
+        .map(|x| {
+            x.values()
+                .filter_map(|x| x["value_percent"].as_str())
+                .filter_map(|x| x.trim_end_matches('%').parse::<u64>().ok())
+                .collect::<Vec<u64>>()
+        })
+        .unwrap_or_default();
+
+    let percent = if channels.is_empty() {
+        0
+    } else {
+        (channels.iter().sum::<u64>() / channels.len() as u64).min(u8::MAX as u64) as u8
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Volume {
+            percent,
+            muted: found["mute"].as_bool().unwrap_or_default(),
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const BACKLIGHT_PATH: &str = "/sys/class/backlight";
+
+pub async fn collect_brightness(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let mut devices = std::fs::read_dir(BACKLIGHT_PATH)?
+        .filter_map(|x| x.ok())
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    devices.sort();
+
+    let device = if let Some(value) = &item.value {
+        devices.iter().find(|x| *x == value)
+    } else {
+        devices.first()
+    }
+    .ok_or_else(|| {
+        anyhow!(
+            "Backlight '{}' could not be found; available backlights: [{}]",
+            item.value.clone().unwrap_or_default(),
+            devices.join(", ")
+        )
+    })?;
+
+    let path = std::path::Path::new(BACKLIGHT_PATH).join(device);
+    let brightness = read_sysfs_u64(path.join("brightness")).unwrap_or(0);
+    let max = read_sysfs_u64(path.join("max_brightness")).unwrap_or(0);
+
+    let percent = if max == 0 {
+        0
+    } else {
+        ((brightness as f64 / max as f64) * 100.0)
+            .round()
+            .min(100.0) as u8
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Brightness { percent },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const NET_WIRELESS_PATH: &str = "/proc/net/wireless";
+
+// the maximum link quality reported by most drivers
+const WIRELESS_MAX_QUALITY: f64 = 70.0;
+
+// returns each wireless interface with its link quality as a percentage
+fn read_net_wireless() -> Result<Vec<(String, u8)>> {
+    let mut ifaces = Vec::new();
+
+    // the first two lines are headers
+    for line in std::fs::read_to_string(NET_WIRELESS_PATH)?.lines().skip(2) {
+        if let Some((name, fields)) = line.split_once(':') {
+            let link = fields
+                .split_whitespace()
+                .nth(1)
+                .and_then(|x| x.trim_end_matches('.').parse::<f64>().ok())
+                .unwrap_or(0.0);
+
+            ifaces.push((
+                name.trim().to_string(),
+                ((link / WIRELESS_MAX_QUALITY) * 100.0)
+                    .round()
+                    .clamp(0.0, 100.0) as u8,
+            ));
+        }
+    }
+
+    Ok(ifaces)
+}
+
+pub async fn collect_wifi(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let ifaces = read_net_wireless()?;
+
+    let iface = if let Some(value) = &item.value {
+        value.clone()
+    } else if let Some((name, _)) = ifaces.first() {
+        name.clone()
+    } else {
+        return Err(anyhow!("No wireless interfaces could be found"));
+    };
+
+    let output = tokio::process::Command::new("iw")
+        .args(["dev", &iface, "link"])
+        .output()
+        .await
+        .map_err(|e| anyhow!("Could not run iw; is it installed? ({})", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Wireless interface '{}' could not be queried: {}",
+            iface,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut ssid = String::new();
+    let mut freq = 0;
+
+    // when not associated, iw prints "Not connected." and the state is left empty.
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("SSID: ") {
+            ssid = value.to_string();
+        } else if let Some(value) = line.strip_prefix("freq: ") {
+            freq = value.parse::<f64>().unwrap_or(0.0) as u32;
+        }
+    }
+
+    let signal = if ssid.is_empty() {
+        0
+    } else {
+        ifaces
+            .iter()
+            .find(|(name, _)| *name == iface)
+            .map_or(0, |(_, signal)| *signal)
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Wifi { ssid, signal, freq },
+        value: Some(iface),
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const BLUEZ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// The connection, name and battery of the BlueZ device with the address or name, or of the
+// first connected one. A device which cannot be found is reported as disconnected.
+fn read_bluetooth(device: Option<String>) -> Result<(bool, String, Option<u8>)> {
+    use dbus::{
+        arg::{prop_cast, PropMap},
+        blocking::{stdintf::org_freedesktop_dbus::ObjectManager, Connection},
+    };
+
+    let conn = Connection::new_system()?;
+    let objects = conn
+        .with_proxy("org.bluez", "/", BLUEZ_TIMEOUT)
+        .get_managed_objects()
+        .map_err(|e| anyhow!("Could not query BlueZ; is bluetoothd running? ({})", e))?;
+
+    let mut devices = objects
+        .values()
+        .filter_map(|interfaces| {
+            let props: &PropMap = interfaces.get("org.bluez.Device1")?;
+            let address = prop_cast::<String>(props, "Address").cloned()?;
+            let name = prop_cast::<String>(props, "Alias")
+                .or(prop_cast::<String>(props, "Name"))
+                .cloned()
+                .unwrap_or(address.clone());
+            let connected = prop_cast::<bool>(props, "Connected").copied() == Some(true);
+            // only devices which report their battery have this interface
+            let battery = interfaces
+                .get("org.bluez.Battery1")
+                .and_then(|props| prop_cast::<u8>(props, "Percentage").copied());
+
+            Some((address, name, connected, battery))
+        })
+        .collect::<Vec<_>>();
+    devices.sort();
+
+    let found = match &device {
+        Some(device) => devices.into_iter().find(|(address, name, ..)| {
+            address.eq_ignore_ascii_case(device) || name.eq_ignore_ascii_case(device)
+        }),
+        None => devices.into_iter().find(|(_, _, connected, _)| *connected),
+    };
+
+    Ok(match found {
+        Some((_, name, connected, battery)) => (connected, name, battery),
+        None => (false, device.unwrap_or_default(), None),
+    })
+}
+
+pub async fn collect_bluetooth(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let device = item.value.clone();
+    let (connected, name, battery) =
+        tokio::task::spawn_blocking(move || read_bluetooth(device)).await??;
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Bluetooth {
+            connected,
+            name,
+            battery,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+const DUNST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+// The notifications shown or waiting to be, and whether dunst is paused. Without a session bus
+// or dunst, there is nothing to show rather than an error, as dunst is often started later.
+fn read_dunst() -> Option<(u32, bool)> {
+    use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+
+    let conn = Connection::new_session().ok()?;
+    let proxy = conn.with_proxy(
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        DUNST_TIMEOUT,
+    );
+    let get = |property: &str| proxy.get::<u32>("org.dunstproject.cmd0", property).ok();
+
+    let count = get("displayedLength")? + get("waitingLength")?;
+    let paused = proxy.get::<bool>("org.dunstproject.cmd0", "paused").ok()?;
+    Some((count, paused))
+}
+
+pub async fn collect_notifications(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let dunst = tokio::task::spawn_blocking(read_dunst).await?;
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Notifications {
+            count: dunst.map_or(0, |x| x.0),
+            paused: dunst.is_some_and(|x| x.1),
+            running: dunst.is_some(),
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+// the command which prints the layout, for compositors yaib cannot ask itself
+const KEYBOARD_COMMAND_ENV: &str = "YAIB_KEYBOARD_COMMAND";
+
+// splits layouts like "de(nodeadkeys)" into the layout and its variant
+fn parse_layout(s: &str) -> (String, Option<String>) {
+    let s = s.trim();
+
+    match s.strip_suffix(')').and_then(|x| x.split_once('(')) {
+        Some((layout, variant)) => (layout.to_string(), Some(variant.to_string())),
+        None => (s.to_string(), None),
+    }
+}
+
+// the stdout of the command, if it could be run and succeeded
+async fn command_stdout(command: &str, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new(command)
+        .args(args)
+        .output()
+        .await
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// The active layout, asked of the command in YAIB_KEYBOARD_COMMAND if it is set, else of
+// sway or Hyprland when running under them, else of X11.
+async fn read_keyboard() -> Result<(String, Option<String>)> {
+    if let Some(command) = std::env::var(KEYBOARD_COMMAND_ENV)
+        .ok()
+        .filter(|x| !x.trim().is_empty())
+    {
+        let parts = command_parts(&command);
+        let args = parts[1..].iter().map(String::as_str).collect::<Vec<&str>>();
+        return command_stdout(&parts[0], &args)
+            .await
+            .map(|x| parse_layout(&x))
+            .ok_or_else(|| anyhow!("{} '{}' failed", KEYBOARD_COMMAND_ENV, command));
+    }
+
+    if std::env::var("SWAYSOCK").is_ok() {
+        let inputs: serde_json::Value = serde_json::from_str(
+            &command_stdout("swaymsg", &["-r", "-t", "get_inputs"])
+                .await
+                .ok_or_else(|| anyhow!("Could not query sway for its inputs"))?,
+        )?;
+
+        // sway only knows the name of the layout, e.g. "German (no dead keys)"
+        return inputs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|input| input["type"] == "keyboard")
+            .find_map(|input| input["xkb_active_layout_name"].as_str())
+            .map(|x| (x.to_string(), None))
+            .ok_or_else(|| anyhow!("sway has no keyboards"));
+    }
+
+    if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
+        let devices: serde_json::Value = serde_json::from_str(
+            &command_stdout("hyprctl", &["devices", "-j"])
+                .await
+                .ok_or_else(|| anyhow!("Could not query Hyprland for its devices"))?,
+        )?;
+        let keyboards = devices["keyboards"].as_array().cloned().unwrap_or_default();
+
+        return keyboards
+            .iter()
+            .find(|keyboard| keyboard["main"] == true)
+            .or(keyboards.first())
+            .and_then(|keyboard| keyboard["active_keymap"].as_str())
+            .map(|x| (x.to_string(), None))
+            .ok_or_else(|| anyhow!("Hyprland has no keyboards"));
+    }
+
+    // xkb-switch knows the active group; setxkbmap only the configured ones, of which the
+    // first is used.
+    if let Some(layout) = command_stdout("xkb-switch", &["-p"]).await {
+        return Ok(parse_layout(&layout));
+    }
 
-// every edit to this must mirror a ModuleType
-#[derive(Debug, Clone)]
-pub enum CollectionType {
-    Static,
-    Dynamic(CommandItem),
-    CPU {
-        count: usize,
-        usage: f64,
-    },
-    Disk {
-        total: usize,
-        usage: usize,
-    },
-    Memory {
-        total: usize,
-        usage: usize,
-        swap_total: usize,
-        swap_usage: usize,
-    },
-    Load(f64, f64, f64),
-    Time(chrono::DateTime<chrono::Local>),
-    Music {
-        artist: String,
-        title: String,
-        pct_played: usize,
-        time_played: usize,
-    },
-    Command(CommandItem),
+    let query = command_stdout("setxkbmap", &["-query"])
+        .await
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not find the keyboard layout; set {} to a command printing it",
+                KEYBOARD_COMMAND_ENV
+            )
+        })?;
+    let field = |name: &str| {
+        query
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|x| x.trim().split(',').next())
+            .map(ToString::to_string)
+            .filter(|x| !x.is_empty())
+    };
+
+    Ok((field("layout:").unwrap_or_default(), field("variant:")))
 }
 
-pub async fn collect_static(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+pub async fn collect_keyboard(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let (layout, variant) = read_keyboard().await?;
+
     let clone = item.clone();
     Ok(s.send(Collection {
         name: item.name,
-        collection_type: CollectionType::Static,
+        collection_type: CollectionType::Keyboard { layout, variant },
         value: item.value,
         format: item.format,
         item: clone,
     })?)
 }
 
-pub async fn collect_time(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+// nvidia-smi reports memory in MiB
+const MIB: u64 = 1024 * 1024;
+
+pub async fn collect_gpu(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let mut args = vec![
+        "--query-gpu=utilization.gpu,memory.used,memory.total,temperature.gpu".to_string(),
+        "--format=csv,noheader,nounits".to_string(),
+    ];
+    if let Some(index) = &item.value {
+        args.push(format!("--id={}", index));
+    }
+
+    let output = tokio::process::Command::new("nvidia-smi")
+        .args(&args)
+        .output()
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Could not run nvidia-smi; is an NVIDIA driver installed? ({})",
+                e
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-smi could not query the GPU: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+
+    // one line per GPU; the first is used unless the value picked one
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fields = stdout
+        .lines()
+        .next()
+        .map(|line| {
+            line.split(',')
+                .map(|x| x.trim().parse::<f64>().unwrap_or_default())
+                .collect::<Vec<f64>>()
+        })
+        .filter(|fields| fields.len() == 4)
+        .ok_or_else(|| anyhow!("Malformed output from nvidia-smi: {}", stdout.trim()))?;
+
     let clone = item.clone();
     Ok(s.send(Collection {
         name: item.name,
-        collection_type: CollectionType::Time(chrono::Local::now()),
+        collection_type: CollectionType::Gpu {
+            usage: fields[0],
+            mem_used: fields[1] as u64 * MIB,
+            mem_total: fields[2] as u64 * MIB,
+            temp: fields[3],
+        },
         value: item.value,
         format: item.format,
         item: clone,
     })?)
 }
 
-pub async fn collect_load(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let avg = mprober_lib::load_average::get_load_average()?;
-    let clone = item.clone();
+const PROC_PATH: &str = "/proc";
+
+// Processes are the numeric directories of /proc; the running ones are counted by the kernel
+// in /proc/stat, so no process needs to be read.
+pub async fn collect_processes(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let total = std::fs::read_dir(PROC_PATH)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|c| c.is_ascii_digit()))
+        })
+        .count();
 
+    let running = std::fs::read_to_string(PROC_STAT_PATH)?
+        .lines()
+        .find_map(|line| line.strip_prefix("procs_running "))
+        .and_then(|x| x.trim().parse::<usize>().ok())
+        .ok_or_else(|| anyhow!("No running processes in {}", PROC_STAT_PATH))?;
+
+    let clone = item.clone();
     Ok(s.send(Collection {
         name: item.name,
-        collection_type: CollectionType::Load(avg.one, avg.five, avg.fifteen),
+        collection_type: CollectionType::Processes { total, running },
         value: item.value,
         format: item.format,
         item: clone,
     })?)
 }
 
-pub async fn collect_cpu(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let avg = mprober_lib::cpu::get_all_cpu_utilization_in_percentage(
-        false,
-        std::time::Duration::from_millis(100),
-    )?;
+const PROC_UPTIME_PATH: &str = "/proc/uptime";
 
-    let count = avg.len();
-    let avg = avg.iter().fold(0.0, |acc, item| item + acc) / count as f64;
-    let clone = item.clone();
+pub async fn collect_uptime(s: CollectionSender, item: ConfigItem) -> Result<()> {
+    let seconds = std::fs::read_to_string(PROC_UPTIME_PATH)?
+        .split_whitespace()
+        .next()
+        .and_then(|x| x.parse::<f64>().ok())
+        .ok_or_else(|| anyhow!("Malformed uptime in {}", PROC_UPTIME_PATH))?;
 
+    let clone = item.clone();
     Ok(s.send(Collection {
         name: item.name,
-        collection_type: CollectionType::CPU {
-            count,
-            usage: avg * 100.0,
+        collection_type: CollectionType::Uptime {
+            seconds: seconds as u64,
         },
         value: item.value,
         format: item.format,
@@ -282,130 +3532,748 @@ pub async fn collect_cpu(s: UnboundedSender<Collection>, item: ConfigItem) -> Re
     })?)
 }
 
-pub async fn collect_memory(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let mem = mprober_lib::memory::free()?;
-    let clone = item.clone();
+pub async fn collect_status(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    let lock = state.lock().await;
+    let collection_type = CollectionType::Status {
+        collectors: lock.collectors,
+        errors: lock.errors.len(),
+        lag: lock.last_frame.map(|x| chrono::Local::now() - x),
+    };
+    drop(lock);
 
+    let clone = item.clone();
     Ok(s.send(Collection {
         name: item.name,
-        collection_type: CollectionType::Memory {
-            total: mem.mem.total,
-            usage: mem.mem.used,
-            swap_total: mem.swap.total,
-            swap_usage: mem.swap.used,
-        },
+        collection_type,
         value: item.value,
         format: item.format,
         item: clone,
     })?)
 }
 
-pub async fn collect_disk(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let clone = item.clone();
-    if let Some(value) = item.value {
-        let vols = mprober_lib::volume::get_volumes()?;
-        let mut target: Option<mprober_lib::volume::Volume> = None;
+// The end of a countdown started at the given time. The value is a date and time, as RFC 3339
+// or `YYYY-MM-DD HH:MM[:SS]` in local time; a time of day, `HH:MM[:SS]`, which is the next
+// time the clock shows it; or a duration from the start, like `25m`.
+pub fn countdown_end(
+    value: &str,
+    start: chrono::DateTime<chrono::Local>,
+) -> Result<chrono::DateTime<chrono::Local>> {
+    use chrono::{NaiveDateTime, NaiveTime, TimeZone};
 
-        for vol in vols {
-            if vol.points.contains(&value) {
-                target = Some(vol);
-                break;
-            }
+    let value = value.trim();
+
+    if let Ok(end) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(end.with_timezone(&chrono::Local));
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(end) = NaiveDateTime::parse_from_str(value, format) {
+            return chrono::Local
+                .from_local_datetime(&end)
+                .earliest()
+                .ok_or_else(|| anyhow!("'{}' does not exist in the local timezone", value));
         }
+    }
 
-        if let Some(target) = target {
-            Ok(s.send(Collection {
-                name: item.name,
-                collection_type: CollectionType::Disk {
-                    total: target.size as usize,
-                    usage: target.used as usize,
-                },
-                value: Some(value),
-                format: item.format,
-                item: clone,
-            })?)
-        } else {
-            Err(anyhow!("Volume could not be found"))
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+            let mut date = start.date_naive();
+            if time <= start.time() {
+                date = date.succ_opt().unwrap_or(date);
+            }
+
+            return chrono::Local
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .ok_or_else(|| anyhow!("'{}' does not exist in the local timezone", value));
         }
-    } else {
-        Err(anyhow!(
-            "Value must be provided and must point at a mount point"
-        ))
     }
+
+    // anything without a number and unit parses as an empty duration, and is not one
+    fancy_duration::FancyDuration::<chrono::Duration>::parse(value)
+        .ok()
+        .map(|duration| duration.duration())
+        .filter(|duration| *duration > chrono::Duration::zero())
+        .map(|duration| start + duration)
+        .ok_or_else(|| {
+            anyhow!(
+                "'{}' is neither a time, a date and time, nor a duration",
+                value
+            )
+        })
 }
 
-pub async fn collect_music(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    if let Ok(player) = mpris::PlayerFinder::new()?.find_active() {
-        if player.is_running() {
-            if let Ok(meta) = player.get_metadata() {
-                let clone = item.clone();
-                let position = player.get_position().unwrap_or_default();
-                s.send(Collection {
-                    name: item.name,
-                    collection_type: CollectionType::Music {
-                        artist: meta.artists().map_or_else(String::new, |x| x.join(", ")),
-                        title: meta.title().unwrap_or_default().to_string(),
-                        pct_played: meta.length().map_or_else(
-                            || 100,
-                            |length| (position.as_secs() / length.as_secs()) as usize,
-                        ),
-                        time_played: position.as_secs() as usize,
-                    },
-                    format: item.format,
-                    item: clone,
-                    value: None,
-                })?;
-            }
+// Countdowns start when the block is first collected, and start over when their value changes,
+// e.g. by editing the configuration.
+pub async fn collect_countdown(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    let value = item.value.clone().unwrap_or_default();
+    let now = chrono::Local::now();
+
+    let mut lock = state.lock().await;
+    let end = match lock.countdown.get(&item.name) {
+        Some((started, end)) if *started == value => *end,
+        _ => {
+            let end = countdown_end(&value, now)?;
+            lock.countdown.insert(item.name.clone(), (value, end));
+            end
         }
+    };
+    drop(lock);
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name.clone(),
+        collection_type: CollectionType::Countdown {
+            remaining: (end - now).max(chrono::Duration::zero()),
+            label: item.name,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+// answers over both IPv4 and IPv6, unlike api.ipify.org, which only has IPv4 addresses
+pub const DEFAULT_PUBLIC_IP_ENDPOINT: &str = "https://api64.ipify.org";
+
+// asks the endpoint for the address it sees over IPv4 or IPv6; failures of any kind are None.
+async fn fetch_public_ip(endpoint: &str, family: &str) -> Option<String> {
+    let output = tokio::process::Command::new("curl")
+        .args(["-s", "-f", family, "--max-time", "10", endpoint])
+        .output()
+        .await
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
     }
 
-    Ok(())
+    // anything but a bare address, like an error page, is not trusted
+    let address = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    address
+        .parse::<std::net::IpAddr>()
+        .is_ok()
+        .then_some(address)
 }
 
-pub async fn collect_command(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+pub async fn collect_public_ip(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    let endpoint = item
+        .value
+        .clone()
+        .unwrap_or(DEFAULT_PUBLIC_IP_ENDPOINT.to_string());
+
+    let (v4, v6) = tokio::join!(
+        fetch_public_ip(&endpoint, "-4"),
+        fetch_public_ip(&endpoint, "-6")
+    );
+
+    // a failed lookup keeps showing the last address found, rather than blanking the block.
+    let mut lock = state.lock().await;
+    let cached = lock.public_ip.entry(item.name.clone()).or_default();
+    let sample = PublicIpSample {
+        v4: v4.or(cached.v4.clone()),
+        v6: v6.or(cached.v6.clone()),
+    };
+    *cached = sample.clone();
+    drop(lock);
+
+    if sample.v4.is_none() && sample.v6.is_none() {
+        return Err(anyhow!(
+            "Public IP address could not be fetched from '{}'; is curl installed?",
+            endpoint
+        ));
+    }
+
     let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::PublicIp {
+            v4: sample.v4,
+            v6: sample.v6,
+        },
+        value: Some(endpoint),
+        format: item.format,
+        item: clone,
+    })?)
+}
 
-    if let Some(value) = item.value {
-        let parts = value
-            .clone()
-            .split(" ")
-            .map(ToString::to_string)
-            .collect::<Vec<String>>();
-        let command: CommandItem = serde_json::from_slice(
-            &tokio::process::Command::new(parts[0].clone())
-                .args(&parts[1..parts.len()])
-                .output()
+pub const DEFAULT_WTTR_URL: &str = "https://wttr.in";
+pub const DEFAULT_OPENWEATHERMAP_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
+
+// curl config strings are quoted, with quotes and backslashes escaped
+fn curl_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Percent-encodes the text as one segment of a URL's path, leaving the characters a segment
+// may hold as they are, like wttr.in's ~ and @ prefixes and its + for spaces.
+fn encode_path_segment(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'='
+            | b':'
+            | b'@' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Fetches the JSON at the URL, with each parameter url encoded into the query. The request is
+// handed to curl as a config on stdin, so API keys never show up in the process list.
+async fn fetch_json(url: &str, params: &[(&str, &str)]) -> Result<serde_json::Value> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut config = format!("url = {}\n", curl_quote(url));
+    if !params.is_empty() {
+        config.push_str("get\n");
+    }
+    for (key, value) in params {
+        config.push_str(&format!(
+            "data-urlencode = {}\n",
+            curl_quote(&format!("{}={}", key, value))
+        ));
+    }
+
+    let mut child = tokio::process::Command::new("curl")
+        .args(["-s", "-f", "-L", "--max-time", "10", "-K", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("curl could not be run: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("curl exited with {}", output.status));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+async fn fetch_weather(item: &ConfigItem) -> Result<WeatherSample> {
+    let location = item.value.clone().unwrap_or_default();
+
+    match item.provider.unwrap_or_default() {
+        WeatherProvider::Wttr => {
+            // without a location, wttr.in guesses one from the address asking
+            let base = item.url.clone().unwrap_or(DEFAULT_WTTR_URL.to_string());
+            let url = format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                encode_path_segment(&location)
+            );
+            let json = fetch_json(&url, &[("format", "j1")]).await?;
+
+            let current = &json["current_condition"][0];
+            Ok(WeatherSample {
+                temp: current["temp_C"]
+                    .as_str()
+                    .and_then(|x| x.parse().ok())
+                    .ok_or_else(|| anyhow!("no temperature in the response of {}", url))?,
+                condition: current["weatherDesc"][0]["value"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                location: json["nearest_area"][0]["areaName"][0]["value"]
+                    .as_str()
+                    .map_or(location, |x| x.to_string()),
+            })
+        }
+        WeatherProvider::OpenWeatherMap => {
+            let url = item
+                .url
+                .clone()
+                .unwrap_or(DEFAULT_OPENWEATHERMAP_URL.to_string());
+            let api_key_env = item.api_key_env.clone().unwrap_or_default();
+            let api_key = std::env::var(&api_key_env)
+                .map_err(|_| anyhow!("Environment variable '{}' is not set", api_key_env))?;
+            let json = fetch_json(
+                &url,
+                &[("q", &location), ("appid", &api_key), ("units", "metric")],
+            )
+            .await?;
+
+            Ok(WeatherSample {
+                temp: json["main"]["temp"]
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("no temperature in the response of {}", url))?,
+                condition: json["weather"][0]["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                location: json["name"].as_str().map_or(location, |x| x.to_string()),
+            })
+        }
+    }
+}
+
+pub async fn collect_weather(
+    s: CollectionSender,
+    item: ConfigItem,
+    state: ProtectedState,
+) -> Result<()> {
+    // like public addresses, a failed update keeps showing the last reading; it is only an
+    // error when there has never been one.
+    let sample = match fetch_weather(&item).await {
+        Ok(sample) => {
+            state
+                .lock()
                 .await
-                .map(|x| x.stdout)?,
-        )?;
+                .weather
+                .insert(item.name.clone(), sample.clone());
+            sample
+        }
+        Err(e) => state
+            .lock()
+            .await
+            .weather
+            .get(&item.name)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Weather could not be fetched for block '{}': {}",
+                    item.name,
+                    e
+                )
+            })?,
+    };
 
-        let c = command.clone();
-        s.send(Collection {
-            name: command.name,
-            collection_type: CollectionType::Command(c),
-            item: clone,
-            value: Some(command.value),
-            format: None,
-        })?;
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Weather {
+            temp: sample.temp,
+            condition: sample.condition,
+            location: sample.location,
+        },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+pub const DEFAULT_IMAP_PORT: u16 = 993;
+pub const DEFAULT_MAILBOX: &str = "INBOX";
+
+// how long to wait on an IMAP server before giving up on this update
+const IMAP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+// how long to wait on each line from an IMAP server, so a stalled one is given up on
+const IMAP_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// IMAP strings are quoted, with quotes and backslashes escaped
+fn imap_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// A logged in IMAP session, kept between updates. TLS is handled by `openssl s_client`, which
+// is killed when the session is dropped.
+#[derive(Debug)]
+pub struct MailSession {
+    // held only so the process lives, and dies, with the session
+    _child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    lines: tokio::io::Lines<tokio::io::BufReader<tokio::process::ChildStdout>>,
+    tag: usize,
+}
+
+impl MailSession {
+    async fn connect(host: &str, port: u16, user: &str, password: &str) -> Result<Self> {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut child = tokio::process::Command::new("openssl")
+            .args([
+                "s_client",
+                "-quiet",
+                "-verify_return_error",
+                // the certificate must be for the host, not only signed by a trusted CA
+                "-verify_hostname",
+                host,
+                "-connect",
+                &format!("{}:{}", host, port),
+                "-servername",
+                host,
+            ])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("Could not run openssl; is it installed? ({})", e))?;
+
+        let mut session = Self {
+            stdin: child.stdin.take().unwrap(),
+            lines: tokio::io::BufReader::new(child.stdout.take().unwrap()).lines(),
+            _child: child,
+            tag: 0,
+        };
+
+        // the server greets with "* OK"
+        match session.next_line().await? {
+            Some(line) if line.starts_with("* OK") => {}
+            _ => return Err(anyhow!("Could not connect to IMAP server '{}'", host)),
+        }
+
+        session
+            .command(&format!(
+                "LOGIN {} {}",
+                imap_quote(user),
+                imap_quote(password)
+            ))
+            .await?;
+
+        Ok(session)
     }
 
-    Ok(())
+    // the next line from the server, unless it stalls
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        tokio::time::timeout(IMAP_READ_TIMEOUT, self.lines.next_line())
+            .await
+            .map_err(|_| anyhow!("IMAP server stopped responding"))?
+            .map_err(Into::into)
+    }
+
+    // runs the command, returning its untagged responses
+    async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        use tokio::io::AsyncWriteExt;
+
+        self.tag += 1;
+        let tag = format!("y{}", self.tag);
+        self.stdin
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await?;
+        self.stdin.flush().await?;
+
+        let mut responses = Vec::new();
+        while let Some(line) = self.next_line().await? {
+            if let Some(status) = line.strip_prefix(&format!("{} ", tag)) {
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+
+                // the command itself is not echoed, as it may hold the password
+                return Err(anyhow!("IMAP server refused the request: {}", status));
+            }
+
+            responses.push(line);
+        }
+
+        Err(anyhow!("IMAP server closed the connection"))
+    }
+
+    // the unread and total messages in the mailbox
+    async fn status(&mut self, mailbox: &str) -> Result<(u32, u32)> {
+        let responses = self
+            .command(&format!("STATUS {} (UNSEEN MESSAGES)", imap_quote(mailbox)))
+            .await?;
+
+        // e.g. * STATUS INBOX (MESSAGES 231 UNSEEN 4)
+        let items = responses
+            .iter()
+            .find(|x| x.starts_with("* STATUS"))
+            .and_then(|x| x.rsplit_once('('))
+            .map(|(_, items)| items.trim_end_matches(')').to_string())
+            .ok_or_else(|| anyhow!("IMAP server did not report mailbox '{}'", mailbox))?;
+
+        let items = items.split_whitespace().collect::<Vec<&str>>();
+        let count = |name: &str| {
+            items
+                .chunks(2)
+                .find(|x| x[0].eq_ignore_ascii_case(name))
+                .and_then(|x| x.get(1))
+                .and_then(|x| x.parse::<u32>().ok())
+                .unwrap_or(0)
+        };
+
+        Ok((count("UNSEEN"), count("MESSAGES")))
+    }
 }
 
-pub async fn collect_dynamic(
-    s: UnboundedSender<Collection>,
+pub async fn collect_mail(
+    s: CollectionSender,
     item: ConfigItem,
-    commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+    state: ProtectedState,
 ) -> Result<()> {
-    while let Ok(command) = commands.lock().await.try_recv() {
-        let c = command.clone();
-        s.send(Collection {
-            name: command.name,
-            collection_type: CollectionType::Dynamic(c),
-            format: None,
-            item: item.clone(),
-            value: Some(command.value),
-        })?;
+    let Some(value) = &item.value else {
+        return Err(anyhow!(
+            "Value must be provided and must name an IMAP server"
+        ));
+    };
+
+    let (host, port) = match value.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| anyhow!("IMAP port '{}' is not a number", port))?,
+        ),
+        None => (value.as_str(), DEFAULT_IMAP_PORT),
+    };
+
+    let user = item
+        .user
+        .clone()
+        .ok_or_else(|| anyhow!("A user must be provided for IMAP server '{}'", host))?;
+    let password_env = item
+        .password_env
+        .clone()
+        .ok_or_else(|| anyhow!("A password_env must be provided for IMAP server '{}'", host))?;
+    let mailbox = item.mailbox.clone().unwrap_or(DEFAULT_MAILBOX.to_string());
+
+    let session = state
+        .lock()
+        .await
+        .mail
+        .entry(item.name.clone())
+        .or_default()
+        .clone();
+    let mut session = session.lock().await;
+
+    let status = tokio::time::timeout(IMAP_TIMEOUT, async {
+        // a session which has gone away is replaced, once per update
+        if let Some(existing) = session.as_mut() {
+            if let Ok(status) = existing.status(&mailbox).await {
+                return Ok(status);
+            }
+        }
+
+        *session = None;
+        let password = std::env::var(&password_env)
+            .map_err(|_| anyhow!("Environment variable '{}' is not set", password_env))?;
+        let mut new = MailSession::connect(host, port, &user, &password).await?;
+        let status = new.status(&mailbox).await?;
+        *session = Some(new);
+
+        Ok::<_, anyhow::Error>(status)
+    })
+    .await;
+
+    let (unread, total) = match status {
+        Ok(status) => status?,
+        Err(_) => {
+            *session = None;
+            return Err(anyhow!("IMAP server '{}' did not respond", host));
+        }
+    };
+
+    let clone = item.clone();
+    Ok(s.send(Collection {
+        name: item.name,
+        collection_type: CollectionType::Mail { unread, total },
+        value: item.value,
+        format: item.format,
+        item: clone,
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a collection of the block configured by the yaml, under the global placeholders
+    fn collection(
+        yaml: &str,
+        placeholders: Placeholders,
+        collection_type: CollectionType,
+    ) -> Collection {
+        let mut item: ConfigItem = serde_yaml::from_str(yaml).unwrap();
+        item.placeholders = placeholders;
+        Collection {
+            name: item.name.clone(),
+            value: item.value.clone(),
+            format: item.format.clone(),
+            collection_type,
+            item,
+        }
+    }
+
+    fn text(collection: &Collection) -> String {
+        collection
+            .get_formatter(collection.format.clone(), &[])
+            .format()
+    }
+
+    #[test]
+    fn default_formats_with_braces() {
+        let notifications = CollectionType::Notifications {
+            count: 3,
+            paused: true,
+            running: true,
+        };
+        let percent = collection(
+            "{name: n, type: notifications}",
+            Placeholders::Percent,
+            notifications.clone(),
+        );
+        let braces = collection(
+            "{name: n, type: notifications}",
+            Placeholders::Braces,
+            notifications,
+        );
+        assert_eq!(text(&percent), "notifications: 3 (paused)");
+        assert_eq!(text(&braces), "notifications: 3 (paused)");
+    }
+
+    #[test]
+    fn custom_formats_with_braces() {
+        let load = collection(
+            "{name: l, type: load, format: '{1}% %1'}",
+            Placeholders::Braces,
+            CollectionType::Load(0.5, 0.25, 0.125),
+        );
+        assert_eq!(text(&load), "0.5% %1");
+    }
+
+    #[test]
+    fn wifi_empty_format() {
+        let disconnected = CollectionType::Wifi {
+            ssid: String::new(),
+            signal: 0,
+            freq: 0,
+        };
+        let default = collection(
+            "{name: w, type: wifi}",
+            Placeholders::Percent,
+            disconnected.clone(),
+        );
+        let custom = collection(
+            "{name: w, type: wifi, empty_format: 'no wifi'}",
+            Placeholders::Percent,
+            disconnected,
+        );
+        assert_eq!(text(&default), "disconnected");
+        assert_eq!(text(&custom), "no wifi");
+    }
+
+    fn zero(collection_type: CollectionType) -> bool {
+        collection(
+            "{name: z, type: static}",
+            Placeholders::Percent,
+            collection_type,
+        )
+        .zero()
+    }
+
+    #[test]
+    fn zero_is_the_collected_value() {
+        let vpn = |up| CollectionType::Vpn {
+            up,
+            iface: "wg0".to_string(),
+            ip: None,
+        };
+        assert!(!zero(vpn(true)));
+        assert!(zero(vpn(false)));
+
+        let battery = |capacity| CollectionType::Battery {
+            capacity,
+            status: "Full".to_string(),
+            time_remaining: None,
+            power: None,
+        };
+        assert!(!zero(battery(100)));
+        assert!(zero(battery(0)));
+
+        let mail = |unread| CollectionType::Mail { unread, total: 10 };
+        assert!(!zero(mail(2)));
+        assert!(zero(mail(0)));
+
+        let countdown = |seconds| CollectionType::Countdown {
+            remaining: chrono::Duration::seconds(seconds),
+            label: String::new(),
+        };
+        assert!(!zero(countdown(60)));
+        assert!(zero(countdown(0)));
+
+        assert!(!zero(CollectionType::Static));
+        assert!(zero(CollectionType::Load(0.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn zero_commands() {
+        let command = |value: &str, percent| {
+            CollectionType::Command(CommandItem {
+                value: value.to_string(),
+                percent,
+                ..Default::default()
+            })
+        };
+        assert!(zero(command("0", None)));
+        assert!(!zero(command("up", None)));
+        assert!(zero(command("up", Some(0))));
+        assert!(!zero(command("0", Some(5))));
+    }
+
+    #[test]
+    fn hide_when_zero() {
+        let mail = collection(
+            "{name: m, type: mail, hide_when_zero: true}",
+            Placeholders::Percent,
+            CollectionType::Mail {
+                unread: 0,
+                total: 3,
+            },
+        );
+        assert!(mail.hidden());
+        let vpn = collection(
+            "{name: v, type: vpn, hide_when_zero: true}",
+            Placeholders::Percent,
+            CollectionType::Vpn {
+                up: true,
+                iface: "wg0".to_string(),
+                ip: None,
+            },
+        );
+        assert!(!vpn.hidden());
+    }
+
+    #[test]
+    fn weather_locations_are_encoded() {
+        assert_eq!(encode_path_segment("London"), "London");
+        assert_eq!(encode_path_segment("New York"), "New%20York");
+        assert_eq!(encode_path_segment("São Paulo"), "S%C3%A3o%20Paulo");
+        assert_eq!(encode_path_segment("a/b?c#d%e"), "a%2Fb%3Fc%23d%25e");
+        assert_eq!(encode_path_segment("~Eiffel+Tower"), "~Eiffel+Tower");
+        assert_eq!(encode_path_segment("@github.com"), "@github.com");
+    }
+
+    #[tokio::test]
+    async fn blank_command_is_an_error() {
+        let (s, _r) = crate::channel::collection_channel(1);
+        let item: ConfigItem =
+            serde_yaml::from_str("{name: c, type: command, value: ' '}").unwrap();
+        let err = collect_command(s, item).await.unwrap_err();
+        assert_eq!(err.to_string(), "Command block 'c' has an empty command");
     }
-    Ok(())
 }
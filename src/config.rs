@@ -1,39 +1,433 @@
-use crate::collectors::*;
-use anyhow::{anyhow, Result};
+use crate::{
+    channel::{CollectionSender, DEFAULT_CHANNEL_CAPACITY},
+    collectors::*,
+    formatter::byte_unit,
+    state::ProtectedState,
+    unix::SOCKET_PATH,
+};
+use anyhow::{anyhow, Context, Result};
 use chrono::Duration;
 use fancy_duration::FancyDuration;
 use serde_derive::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
 use tokio::sync::{
     mpsc::{UnboundedReceiver, UnboundedSender},
     Mutex,
 };
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Output {
+    #[serde(rename = "i3bar")]
+    #[default]
+    I3bar,
+    #[serde(rename = "waybar")]
+    Waybar,
+    #[serde(rename = "plain")]
+    Plain,
+    #[serde(rename = "lemonbar")]
+    Lemonbar,
+}
+
+// where on the bar blocks are placed, by outputs which can place them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Align {
+    #[serde(rename = "left")]
+    #[default]
+    Left,
+    #[serde(rename = "center")]
+    Center,
+    #[serde(rename = "right")]
+    Right,
+}
+
+pub const DEFAULT_SEPARATOR: &str = " | ";
+
+// how byte counts are shown: decimal units are powers of 1000 (kB, MB), and binary ones
+// powers of 1024 (KiB, MiB).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Units {
+    #[serde(rename = "decimal")]
+    #[default]
+    Decimal,
+    #[serde(rename = "binary")]
+    Binary,
+}
+
+// how formats write their placeholders: %name, or {name} so a % is never mistaken for one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Placeholders {
+    #[serde(rename = "percent")]
+    #[default]
+    Percent,
+    #[serde(rename = "braces")]
+    Braces,
+}
+
+// what becomes of the errors of collectors: logged, also shown in place of the block, or neither
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorDisplay {
+    #[serde(rename = "log")]
+    #[default]
+    Log,
+    #[serde(rename = "block")]
+    Block,
+    #[serde(rename = "ignore")]
+    Ignore,
+}
+
+// when output is flushed: after every frame, or after every line, as with waybar's block per line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Flush {
+    #[serde(rename = "always")]
+    #[default]
+    Always,
+    #[serde(rename = "line")]
+    Line,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<std::path::PathBuf>,
+    #[serde(default)]
     pages: Vec<ConfigPage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     update_interval: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    click_events: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    separator: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    refresh_types: Vec<ModuleType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<Units>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metrics_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_rotate: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    volume_cache: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_capacity: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placeholders: Option<Placeholders>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket: Option<std::path::PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_display: Option<ErrorDisplay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flush: Option<Flush>,
+    // the file loaded and every file it includes
+    #[serde(skip)]
+    files: Vec<std::path::PathBuf>,
 }
 
 impl Config {
+    // a configuration to start from, with a few common blocks using their default formats
+    pub fn sample() -> Self {
+        let item = |name: &str, typ: ModuleType, value: Option<&str>, urgent: bool| ConfigItem {
+            name: name.to_string(),
+            value: value.map(ToString::to_string),
+            format: typ.default_format().map(ToString::to_string),
+            urgency: urgent.then_some((25, 50, 80)),
+            urgency_colors: urgent.then(|| {
+                (
+                    "#66FF66".to_string(),
+                    "#FFFF44".to_string(),
+                    "#FF6666".to_string(),
+                )
+            }),
+            typ,
+            ..Default::default()
+        };
+
+        Self {
+            pages: vec![ConfigPage {
+                items: vec![
+                    item("cpu", ModuleType::CPU, None, true),
+                    item("memory", ModuleType::Memory, None, true),
+                    item("disk", ModuleType::Disk, Some("/"), true),
+                    item("time", ModuleType::Time, None, false),
+                ],
+                ..Default::default()
+            }],
+            update_interval: Some(FancyDuration(Duration::seconds(1))),
+            output: Some(Output::I3bar),
+            ..Default::default()
+        }
+    }
+
+    // Loads the file and any it includes. Files ending in .toml are read as TOML, and any others
+    // as YAML.
     pub fn load(filename: std::path::PathBuf) -> Result<Self> {
+        let mut config = Self::read(
+            &filename,
+            &mut std::collections::BTreeMap::new(),
+            &mut Vec::new(),
+        )?;
+        // pages may all come from includes, but there must be some
+        if config.pages.is_empty() {
+            return Err(anyhow!("{} has no pages", filename.display()));
+        }
+
+        for item in config
+            .pages
+            .iter_mut()
+            .flat_map(|page| page.items.iter_mut())
+        {
+            item.expand_env()?;
+            item.check_colors()?;
+            // blocks without units use the global ones
+            item.units = item.units.or(config.units);
+            item.volume_cache = config
+                .volume_cache
+                .clone()
+                .map_or(DEFAULT_VOLUME_CACHE, |x| x.duration());
+            item.placeholders = config.placeholders.unwrap_or_default();
+        }
+
+        Ok(config)
+    }
+
+    // Errors name the file, with the line and column of the mistake when the parser knows it.
+    fn parse(filename: &std::path::Path) -> Result<Self> {
         let mut io = std::fs::OpenOptions::new();
         io.read(true);
-        let r = io.open(filename)?;
-        Ok(serde_yaml::from_reader(r)?)
+        let mut r = io
+            .open(filename)
+            .with_context(|| format!("Could not read {}", filename.display()))?;
+
+        let parsed: Result<Self> = if filename.extension().is_some_and(|x| x == "toml") {
+            let mut s = String::new();
+            std::io::Read::read_to_string(&mut r, &mut s)
+                .map_err(Into::into)
+                .and_then(|_| Ok(toml::from_str(&s)?))
+        } else {
+            serde_yaml::from_reader(r).map_err(Into::into)
+        };
+
+        parsed.with_context(|| format!("Could not parse {}", filename.display()))
+    }
+
+    // Reads the file with its includes merged in, as if each included file came before it.
+    // Relative includes are found from the directory of the file including them. origins records
+    // the file each block came from, so a name used in two files is reported with both, and
+    // including holds the files being read, so an include cycle is an error rather than a hang.
+    fn read(
+        filename: &std::path::Path,
+        origins: &mut std::collections::BTreeMap<String, std::path::PathBuf>,
+        including: &mut Vec<std::path::PathBuf>,
+    ) -> Result<Self> {
+        let canonical = filename
+            .canonicalize()
+            .unwrap_or_else(|_| filename.to_path_buf());
+        if including.contains(&canonical) {
+            return Err(anyhow!("{} includes itself", filename.display()));
+        }
+
+        let mut own = Self::parse(filename)?;
+        including.push(canonical);
+
+        let dir = filename.parent().unwrap_or(std::path::Path::new(""));
+        let mut config = Self::default();
+        for include in std::mem::take(&mut own.include) {
+            let included = Self::read(&dir.join(include), origins, including)
+                .with_context(|| format!("Included from {}", filename.display()))?;
+            config.merge(included);
+        }
+
+        // the same name twice in one file is left to problems()
+        for item in own.pages.iter().flat_map(|page| page.items.iter()) {
+            match origins.get(&item.name) {
+                Some(origin) if origin != filename => {
+                    return Err(anyhow!(
+                        "Block name '{}' in {} is already used in {}",
+                        item.name,
+                        filename.display(),
+                        origin.display()
+                    ));
+                }
+                _ => {
+                    origins.insert(item.name.clone(), filename.to_path_buf());
+                }
+            }
+        }
+
+        own.files = vec![filename.to_path_buf()];
+        config.merge(own);
+        including.pop();
+
+        Ok(config)
+    }
+
+    // Adds the pages of other to these, page by page, after the items already on them. Settings
+    // set in other replace these.
+    fn merge(&mut self, other: Self) {
+        for (i, page) in other.pages.into_iter().enumerate() {
+            match self.pages.get_mut(i) {
+                Some(merged) => {
+                    merged.items.extend(page.items);
+                    merged.update_interval = page.update_interval.or(merged.update_interval.take());
+                    merged.align = page.align.or(merged.align);
+                }
+                None => self.pages.push(page),
+            }
+        }
+
+        self.update_interval = other.update_interval.or(self.update_interval.take());
+        self.output = other.output.or(self.output);
+        self.click_events = other.click_events.or(self.click_events);
+        self.separator = other.separator.or(self.separator.take());
+        if !other.refresh_types.is_empty() {
+            self.refresh_types = other.refresh_types;
+        }
+        self.units = other.units.or(self.units);
+        self.metrics_port = other.metrics_port.or(self.metrics_port);
+        self.page_rotate = other.page_rotate.or(self.page_rotate.take());
+        self.volume_cache = other.volume_cache.or(self.volume_cache.take());
+        self.channel_capacity = other.channel_capacity.or(self.channel_capacity);
+        self.placeholders = other.placeholders.or(self.placeholders);
+        self.socket = other.socket.or(self.socket.take());
+        self.error_display = other.error_display.or(self.error_display);
+        self.flush = other.flush.or(self.flush);
+        self.files.extend(other.files);
+    }
+
+    // Reloads the configuration whenever the file, or any file it included when this
+    // configuration was loaded, changes, sending each successfully loaded configuration.
+    // Configurations which fail to load are reported and skipped. The watcher stops when it is
+    // dropped.
+    pub fn watch(
+        &self,
+        filename: std::path::PathBuf,
+    ) -> Result<(notify::RecommendedWatcher, UnboundedReceiver<Config>)> {
+        use notify::Watcher;
+
+        let (s, r) = tokio::sync::mpsc::unbounded_channel();
+        let mut files = self.files.clone();
+        if !files.contains(&filename) {
+            files.push(filename.clone());
+        }
+        let names: Vec<_> = files
+            .iter()
+            .filter_map(|x| x.file_name().map(|x| x.to_os_string()))
+            .collect();
+        let path = filename.clone();
+
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                if let Ok(event) = res {
+                    if !(event.kind.is_create() || event.kind.is_modify())
+                        || !event
+                            .paths
+                            .iter()
+                            .any(|x| x.file_name().is_some_and(|x| names.iter().any(|n| n == x)))
+                    {
+                        return;
+                    }
+
+                    match Config::load(path.clone()) {
+                        Ok(config) => {
+                            let problems = config.problems();
+                            if problems.is_empty() {
+                                log::info!("Reloaded configuration from {}", path.display());
+                                let _ = s.send(config);
+                            } else {
+                                log::error!(
+                                    "Could not reload configuration: {}",
+                                    problems.join("; ")
+                                );
+                            }
+                        }
+                        Err(e) => log::error!("Could not reload configuration: {:#}", e),
+                    }
+                }
+            })?;
+
+        // editors often replace files instead of writing them, so the directories are watched.
+        let mut dirs = std::collections::BTreeSet::new();
+        for file in &files {
+            dirs.insert(match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => std::path::PathBuf::from("."),
+            });
+        }
+        for dir in dirs {
+            watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+        }
+
+        Ok((watcher, r))
+    }
+
+    // every problem with the configuration which would fail at runtime
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut names = std::collections::BTreeSet::new();
+
+        for item in self.pages.iter().flat_map(|page| page.items.iter()) {
+            if !names.insert(item.name.clone()) {
+                problems.push(format!("Block name '{}' is used more than once", item.name));
+            }
+
+            if let Err(e) = item.validate() {
+                problems.push(e.to_string());
+            }
+        }
+
+        problems
+    }
+
+    pub fn abort_collectors(&self) {
+        for page in &self.pages {
+            page.abort_collectors();
+        }
+    }
+
+    // Makes the collectors run at their next launch, regardless of their update interval. When
+    // types are given, only collectors of those types are forced.
+    pub fn force_collectors(&mut self, types: Option<&[ModuleType]>) {
+        for item in self.pages.iter_mut().flat_map(|page| page.items.iter_mut()) {
+            if types.is_none_or(|types| types.contains(&item.typ)) {
+                item.force_collector();
+            }
+        }
     }
 
     pub async fn launch_collectors(
         &mut self,
-        s: UnboundedSender<Collection>,
+        s: CollectionSender,
         result: UnboundedSender<Result<()>>,
         commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        state: ProtectedState,
     ) -> Result<()> {
+        let interval = self.update_interval();
+        // every collector launched on this pass reads the same snapshot of the system
+        let snapshot = Snapshot::default();
         for page in &mut self.pages {
-            page.launch_collectors(s.clone(), result.clone(), commands.clone())
-                .await?;
+            page.launch_collectors(
+                s.clone(),
+                result.clone(),
+                commands.clone(),
+                state.clone(),
+                &snapshot,
+                interval,
+            )
+            .await?;
         }
 
+        // for status blocks, which read it on their next collection
+        state.lock().await.collectors = self
+            .pages
+            .iter()
+            .flat_map(|page| page.items.iter())
+            .map(|item| item.tasks.iter().filter(|x| !x.is_finished()).count())
+            .sum();
+
         Ok(())
     }
 
@@ -41,31 +435,196 @@ impl Config {
         self.pages.clone()
     }
 
+    // the item of a block, which for the lines of a split command is the command's
+    pub fn item(&self, name: &str) -> Option<ConfigItem> {
+        let items = || self.pages.iter().flat_map(|page| page.items.iter());
+
+        items()
+            .find(|item| item.name == name)
+            .or_else(|| {
+                items().find(|item| {
+                    item.split_lines()
+                        && name
+                            .strip_prefix(&item.name)
+                            .is_some_and(|x| x.starts_with('.'))
+                })
+            })
+            .cloned()
+    }
+
+    pub fn output(&self) -> Output {
+        self.output.unwrap_or_default()
+    }
+
+    // the text between blocks of the plain output
+    pub fn separator(&self) -> String {
+        self.separator
+            .clone()
+            .unwrap_or(DEFAULT_SEPARATOR.to_string())
+    }
+
+    pub fn click_events(&self) -> bool {
+        // waybar does not send clicks on stdin
+        self.click_events.unwrap_or(self.output() == Output::I3bar)
+    }
+
+    // how many collections may wait on the bar before older ones are dropped
+    pub fn channel_capacity(&self) -> usize {
+        self.channel_capacity.unwrap_or(DEFAULT_CHANNEL_CAPACITY)
+    }
+
+    pub fn flush(&self) -> Flush {
+        self.flush.unwrap_or_default()
+    }
+
+    pub fn error_display(&self) -> ErrorDisplay {
+        self.error_display.unwrap_or_default()
+    }
+
+    // the unix socket dynamic blocks are written to
+    pub fn socket(&self) -> std::path::PathBuf {
+        self.socket.clone().unwrap_or_else(|| SOCKET_PATH.into())
+    }
+
+    // the port metrics are served on, if any
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
+    // how long each page is shown before flipping to the next, if pages flip by themselves
+    pub fn page_rotate(&self) -> Option<chrono::Duration> {
+        self.page_rotate.clone().map(|x| x.duration())
+    }
+
+    // the page after the one given; the last page is followed by the first
+    pub fn next_page(&self, page: usize) -> usize {
+        (page + 1) % self.pages.len().max(1)
+    }
+
+    // the module types whose collectors are forced to run on SIGUSR2
+    pub fn refresh_types(&self) -> Vec<ModuleType> {
+        self.refresh_types.clone()
+    }
+
     pub fn update_interval(&self) -> chrono::Duration {
         self.update_interval
             .clone()
             .unwrap_or(FancyDuration(chrono::Duration::seconds(1)))
             .duration()
     }
+
+    pub fn page_update_interval(&self, page: usize) -> chrono::Duration {
+        self.pages
+            .get(page)
+            .and_then(|page| page.update_interval())
+            .unwrap_or(self.update_interval())
+    }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct ConfigPage(Vec<ConfigItem>);
+// A page is either a list of items, or a map of the items and settings for the page.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigPage {
+    items: Vec<ConfigItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    update_interval: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    align: Option<Align>,
+}
+
+impl<'de> serde::Deserialize<'de> for ConfigPage {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Page {
+            items: Vec<ConfigItem>,
+            update_interval: Option<FancyDuration<Duration>>,
+            align: Option<Align>,
+        }
+
+        struct PageVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for PageVisitor {
+            type Value = ConfigPage;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a list of items, or a map with a list of items")
+            }
+
+            fn visit_seq<A>(self, seq: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                Ok(ConfigPage {
+                    items: serde::Deserialize::deserialize(
+                        serde::de::value::SeqAccessDeserializer::new(seq),
+                    )?,
+                    update_interval: None,
+                    align: None,
+                })
+            }
+
+            fn visit_map<A>(self, map: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let page: Page = serde::Deserialize::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(map),
+                )?;
+
+                Ok(ConfigPage {
+                    items: page.items,
+                    update_interval: page.update_interval,
+                    align: page.align,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(PageVisitor)
+    }
+}
 
 impl ConfigPage {
+    pub fn align(&self) -> Align {
+        self.align.unwrap_or_default()
+    }
+
     pub fn items(&self) -> Vec<ConfigItem> {
-        self.0.clone()
+        self.items.clone()
+    }
+
+    pub fn update_interval(&self) -> Option<chrono::Duration> {
+        self.update_interval.clone().map(|x| x.duration())
+    }
+
+    pub fn abort_collectors(&self) {
+        for item in &self.items {
+            item.abort_collector();
+        }
     }
 
     pub async fn launch_collectors(
         &mut self,
-        s: UnboundedSender<Collection>,
+        s: CollectionSender,
         result: UnboundedSender<Result<()>>,
         commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        state: ProtectedState,
+        snapshot: &Snapshot,
+        interval: chrono::Duration,
     ) -> Result<()> {
-        for item in &mut self.0 {
-            item.launch_collector(s.clone(), result.clone(), commands.clone())
-                .await?;
+        // items without an interval use the page's, and pages without one use the global one.
+        let interval = self.update_interval().unwrap_or(interval);
+        for item in &mut self.items {
+            item.launch_collector(
+                s.clone(),
+                result.clone(),
+                commands.clone(),
+                state.clone(),
+                snapshot,
+                interval,
+            )
+            .await?;
         }
 
         Ok(())
@@ -73,7 +632,7 @@ impl ConfigPage {
 }
 
 // every edit to this must mirror a CollectionType
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ModuleType {
     #[serde(rename = "static")]
     Static,
@@ -94,6 +653,120 @@ pub enum ModuleType {
     Music,
     #[serde(rename = "command")]
     Command,
+    #[serde(rename = "battery")]
+    Battery,
+    #[serde(rename = "network", alias = "net")]
+    Network,
+    #[serde(rename = "temperature", alias = "temp")]
+    Temperature,
+    #[serde(rename = "volume")]
+    Volume,
+    #[serde(rename = "brightness", alias = "backlight")]
+    Brightness,
+    #[serde(rename = "wifi", alias = "wireless")]
+    Wifi,
+    #[serde(rename = "uptime")]
+    Uptime,
+    #[serde(rename = "status", alias = "self")]
+    Status,
+    #[serde(rename = "public_ip", alias = "ip")]
+    PublicIp,
+    #[serde(rename = "mail", alias = "imap")]
+    Mail,
+    #[serde(rename = "notifications", alias = "dunst")]
+    Notifications,
+    #[serde(rename = "bluetooth", alias = "bt")]
+    Bluetooth,
+    #[serde(rename = "keyboard", alias = "kbd")]
+    Keyboard,
+    #[serde(rename = "processes", alias = "procs")]
+    Processes,
+    #[serde(rename = "gpu", alias = "nvidia")]
+    Gpu,
+    #[serde(rename = "fan")]
+    Fan,
+    #[serde(rename = "vpn", alias = "tunnel")]
+    Vpn,
+    #[serde(rename = "weather")]
+    Weather,
+    #[serde(rename = "countdown", alias = "timer")]
+    Countdown,
+    #[serde(rename = "disk_io", alias = "io")]
+    DiskIo,
+}
+
+impl ModuleType {
+    // the name the type is written as in configurations, e.g. disk_io
+    pub fn name(&self) -> &'static str {
+        MODULES
+            .iter()
+            .find(|x| x.typ == *self)
+            .map_or("unknown", |x| x.name)
+    }
+
+    // the format used by blocks which do not set one; some types have no format.
+    pub fn default_format(&self) -> Option<&'static str> {
+        match self {
+            Self::Static | Self::Command => None,
+            Self::Dynamic => Some("%value"),
+            Self::CPU => Some("cpus: %count, usage: %usage"),
+            Self::Disk | Self::Memory => Some("total: %total, usage: %usage"),
+            Self::Load => Some("%1, %5, %15"),
+            Self::Time => Some("%m/%d %H:%M"),
+            Self::Music => Some("music: %artist - %title"),
+            Self::Battery => Some("%capacity% (%status)"),
+            Self::Network => Some("%iface: down: %rx, up: %tx"),
+            Self::Temperature => Some("%label: %celsius°C"),
+            Self::Volume => Some("vol: %percent%%%mute"),
+            Self::Brightness => Some("brightness: %percent%"),
+            Self::Wifi => Some("%ssid: %signal%"),
+            Self::Uptime => Some("up %dd %hh %mm"),
+            Self::Status => Some("yaib: %collectors running, %errors failing"),
+            Self::PublicIp => Some("ip: %v4"),
+            Self::Mail => Some("mail: %unread"),
+            Self::Notifications => Some("notifications: %count{if paused==yes} (paused){endif}"),
+            Self::Bluetooth => Some("%name: %battery%"),
+            Self::Keyboard => Some("kbd: %layout"),
+            Self::Processes => Some("procs: %running/%total"),
+            Self::Gpu => Some("gpu: %usage% %temp°C"),
+            Self::Fan => Some("fan: %rpm rpm"),
+            Self::Vpn => Some("vpn: %status"),
+            Self::Weather => Some("%location: %temp°C %condition"),
+            Self::Countdown => Some("%label: %remaining"),
+            Self::DiskIo => Some("%device: read: %read, write: %write"),
+        }
+    }
+
+    // the format used by empty blocks which do not set an empty_format, when it is not their
+    // format; music shows nothing while nothing is playing, and wifi that it is disconnected.
+    pub fn default_empty_format(&self) -> Option<&'static str> {
+        match self {
+            Self::Music => Some(""),
+            Self::Wifi => Some("disconnected"),
+            _ => None,
+        }
+    }
+
+    // the interval for types which should not follow the page or global one, unless the block
+    // sets its own.
+    pub fn default_update_interval(&self) -> Option<chrono::Duration> {
+        match self {
+            // these go over the network, and the public IP service is rate limited
+            Self::PublicIp => Some(chrono::Duration::minutes(10)),
+            Self::Mail => Some(chrono::Duration::minutes(5)),
+            Self::Weather => Some(chrono::Duration::minutes(10)),
+            _ => None,
+        }
+    }
+
+    // the shortest interval of types whose services must not be asked too often, whatever
+    // the block sets.
+    pub fn min_update_interval(&self) -> Option<chrono::Duration> {
+        match self {
+            Self::Weather => Some(chrono::Duration::minutes(10)),
+            _ => None,
+        }
+    }
 }
 
 impl From<CollectionType> for ModuleType {
@@ -108,15 +781,74 @@ impl From<CollectionType> for ModuleType {
             CollectionType::Time(..) => Self::Time,
             CollectionType::Music { .. } => Self::Music,
             CollectionType::Command(..) => Self::Command,
+            CollectionType::Battery { .. } => Self::Battery,
+            CollectionType::Network { .. } => Self::Network,
+            CollectionType::Temperature { .. } => Self::Temperature,
+            CollectionType::Volume { .. } => Self::Volume,
+            CollectionType::Brightness { .. } => Self::Brightness,
+            CollectionType::Wifi { .. } => Self::Wifi,
+            CollectionType::Uptime { .. } => Self::Uptime,
+            CollectionType::Status { .. } => Self::Status,
+            CollectionType::PublicIp { .. } => Self::PublicIp,
+            CollectionType::Mail { .. } => Self::Mail,
+            CollectionType::Notifications { .. } => Self::Notifications,
+            CollectionType::Bluetooth { .. } => Self::Bluetooth,
+            CollectionType::Keyboard { .. } => Self::Keyboard,
+            CollectionType::Processes { .. } => Self::Processes,
+            CollectionType::Gpu { .. } => Self::Gpu,
+            CollectionType::Fan { .. } => Self::Fan,
+            CollectionType::Vpn { .. } => Self::Vpn,
+            CollectionType::Weather { .. } => Self::Weather,
+            CollectionType::Countdown { .. } => Self::Countdown,
+            CollectionType::DiskIo { .. } => Self::DiskIo,
         }
     }
 }
 
+// the service weather blocks ask
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WeatherProvider {
+    #[serde(rename = "wttr")]
+    #[default]
+    Wttr,
+    #[serde(rename = "openweathermap")]
+    OpenWeatherMap,
+}
+
+// What a command block's command emits: a JSON object with its value, or a whole block in
+// JSON, which is shown as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandOutput {
+    #[serde(rename = "value")]
+    #[default]
+    Value,
+    #[serde(rename = "json")]
+    Json,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CommandItem {
     pub name: String,
     pub value: String,
     pub percent: Option<u64>,
+
+    // how the command of a command block exited, and what it wrote to stderr
+    #[serde(skip)]
+    pub exit: Option<i32>,
+    #[serde(skip)]
+    pub stderr: String,
+    // the line of the output of a command block which splits its lines
+    #[serde(skip)]
+    pub line: Option<usize>,
+    // the block emitted by a command with JSON output
+    #[serde(skip)]
+    pub block: Option<crate::bar::Block>,
+}
+
+impl CommandItem {
+    pub fn failed(&self) -> bool {
+        self.exit.is_some_and(|x| x != 0)
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -124,78 +856,710 @@ pub struct ConfigItem {
     pub name: String,
     #[serde(rename = "type")]
     pub typ: ModuleType,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hide_when_empty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hide_when_zero: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgent_when_read_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub urgency: Option<(u8, u8, u8)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub urgency_colors: Option<(String, String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gradient: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_separator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icons: Option<std::collections::BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub update_interval: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bar_width: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bar_chars: Option<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_click: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markup: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator_block_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_top: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_bottom: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_left: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_right: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mailbox: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<WeatherProvider>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<Units>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_unit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_precision: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_window: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smooth: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<FancyDuration<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_lines: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_output: Option<CommandOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub align: Option<Align>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_width: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_align: Option<Align>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll_width: Option<usize>,
 
     #[serde(skip)]
     pub(crate) last_updated: chrono::DateTime<chrono::Local>,
+    // the collector's failures since it last succeeded, which back off its interval
+    #[serde(skip)]
+    pub(crate) failures: Arc<AtomicU32>,
+    // how long disk blocks reuse the volumes read by another, from the global volume_cache
+    #[serde(skip)]
+    pub(crate) volume_cache: chrono::Duration,
+    // how the block's formats write placeholders, from the global placeholders
+    #[serde(skip)]
+    pub(crate) placeholders: Placeholders,
+    #[serde(skip)]
+    pub(crate) tasks: Vec<tokio::task::AbortHandle>,
+}
+
+// The files a configuration is looked for in, in order: $XDG_CONFIG_HOME/yaib/config.yaml, then
+// ~/.config/yaib/config.yaml, then the older yaib.config.yaml and yaib.config.toml names in
+// those same directories. XDG_CONFIG_HOME is ignored unless it is an absolute path.
+pub fn config_paths(
+    xdg_config_home: Option<std::path::PathBuf>,
+    home: Option<std::path::PathBuf>,
+) -> Vec<std::path::PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = xdg_config_home.filter(|x| x.is_absolute()) {
+        dirs.push(dir.join("yaib"));
+    }
+    if let Some(home) = home {
+        let dir = home.join(".config").join("yaib");
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    ["config.yaml", "yaib.config.yaml", "yaib.config.toml"]
+        .iter()
+        .flat_map(|name| dirs.iter().map(move |dir| dir.join(name)))
+        .collect()
+}
+
+// the first of the paths that exists, or an error listing every path tried
+pub fn find_config(
+    paths: &[std::path::PathBuf],
+    exists: impl Fn(&std::path::Path) -> bool,
+) -> Result<std::path::PathBuf> {
+    if let Some(path) = paths.iter().find(|x| exists(x)) {
+        return Ok(path.clone());
+    }
+
+    let mut message = String::from("No configuration file was found; tried:");
+    for path in paths {
+        message += &format!("\n    {}", path.display());
+    }
+    message += "\nPass one with --config, or set YAIB_CONFIG.";
+    Err(anyhow!(message))
+}
+
+// Expands $VAR and ${VAR} references to environment variables. Unset variables are an error,
+// unless a default is given with ${VAR:-default}. $$ is a literal $.
+pub fn expand_env(s: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let (name, default) = match chars.peek() {
+            Some('$') => {
+                chars.next();
+                expanded.push('$');
+                continue;
+            }
+            Some('{') => {
+                chars.next();
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(anyhow!("Unterminated '${{' in '{}'", s)),
+                    }
+                }
+
+                match inner.split_once(":-") {
+                    Some((name, default)) => (name.to_string(), Some(default.to_string())),
+                    None => (inner, None),
+                }
+            }
+            _ => {
+                let mut name = String::new();
+                while let Some(c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    name.push(*c);
+                    chars.next();
+                }
+
+                // a lone $ is left alone
+                if name.is_empty() {
+                    expanded.push('$');
+                    continue;
+                }
+
+                (name, None)
+            }
+        };
+
+        match (std::env::var(&name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(&default),
+            (Err(_), None) => return Err(anyhow!("Environment variable '{}' is not set", name)),
+        }
+    }
+
+    Ok(expanded)
+}
+
+// #rgb, #rrggbb, or #rrggbbaa with an alpha channel
+fn is_color(s: &str) -> bool {
+    s.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+// the longest a failing collector's interval is backed off to, unless it is already longer
+const MAX_BACKOFF: i64 = 5 * 60 * 1000;
+
+// The context of the errors of collectors, naming the block which failed, so they can be shown
+// in its place.
+#[derive(Debug, Clone)]
+pub struct BlockFailed {
+    pub name: String,
+    pub typ: ModuleType,
+}
+
+impl std::fmt::Display for BlockFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Block '{}' ({}) failed", self.name, self.typ.name())
+    }
 }
 
-async fn spawn(
+// spawns the collector and reports its result, counting its consecutive failures; the returned
+// handle cancels it.
+fn spawn(
     s: UnboundedSender<Result<()>>,
+    failures: Arc<AtomicU32>,
+    failed: BlockFailed,
     f: impl std::future::Future<Output = Result<()>> + Send + 'static,
-) -> Result<()> {
-    Ok(s.send(tokio::spawn(f).await?)?)
+) -> tokio::task::AbortHandle {
+    let handle = tokio::spawn(f);
+    let abort = handle.abort_handle();
+
+    tokio::spawn(async move {
+        // cancelled collectors have nothing to report
+        if let Ok(res) = handle.await {
+            if res.is_ok() {
+                failures.store(0, Ordering::Relaxed);
+            } else {
+                failures.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let _ = s.send(res.context(failed));
+        }
+    });
+
+    abort
 }
 
 impl ConfigItem {
+    // Colors are handed to the bar as they are, which draws a malformed one wrong or not at
+    // all, so they are checked as the configuration is loaded.
+    fn check_colors(&self) -> Result<()> {
+        let mut colors = vec![
+            ("background", self.background.as_ref()),
+            ("border", self.border.as_ref()),
+        ];
+        if let Some((warn, high, critical)) = &self.urgency_colors {
+            colors.extend([
+                ("urgency_colors", Some(warn)),
+                ("urgency_colors", Some(high)),
+                ("urgency_colors", Some(critical)),
+            ]);
+        }
+        if let Some(gradient) = &self.gradient {
+            if !matches!(gradient.len(), 2 | 3) {
+                return Err(anyhow!(
+                    "Block '{}' has a gradient of {} colors, which must have 2 or 3",
+                    self.name,
+                    gradient.len()
+                ));
+            }
+            colors.extend(gradient.iter().map(|color| ("gradient", Some(color))));
+        }
+
+        for (field, color) in colors {
+            if let Some(color) = color.filter(|color| !is_color(color)) {
+                return Err(anyhow!(
+                    "Block '{}' has {} '{}', which must be a color like #rgb, #rrggbb or #rrggbbaa",
+                    self.name,
+                    field,
+                    color
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn split_lines(&self) -> bool {
+        self.split_lines.unwrap_or_default()
+    }
+
+    // checks the byte_unit, and the fields required by the block's type
+    pub fn validate(&self) -> Result<()> {
+        if let Some(unit) = self.byte_unit.as_ref().filter(|x| byte_unit(x).is_none()) {
+            return Err(anyhow!(
+                "Block '{}' has byte_unit '{}', which must be a unit like MB or GiB",
+                self.name,
+                unit
+            ));
+        }
+
+        if self.peak_window == Some(0) {
+            return Err(anyhow!(
+                "Block '{}' has a peak_window of 0, which must be at least 1",
+                self.name
+            ));
+        }
+
+        // at 1, the first value would be shown forever
+        if let Some(smooth) = self.smooth.filter(|x| !(0.0..1.0).contains(x)) {
+            return Err(anyhow!(
+                "Block '{}' has smooth {}, which must be at least 0 and under 1",
+                self.name,
+                smooth
+            ));
+        }
+
+        match self.typ {
+            ModuleType::Static if self.value.is_none() => {
+                Err(anyhow!("Static block '{}' must have a value", self.name))
+            }
+            ModuleType::Command
+                if self.value.as_deref().is_none_or(|x| x.trim().is_empty()) =>
+            {
+                Err(anyhow!(
+                    "Command block '{}' must have a value with the command to run",
+                    self.name
+                ))
+            }
+            ModuleType::Network if self.value.is_none() => Err(anyhow!(
+                "Network block '{}' must have a value with an interface name",
+                self.name
+            )),
+            ModuleType::DiskIo if self.value.is_none() => Err(anyhow!(
+                "Disk I/O block '{}' must have a value with a block device",
+                self.name
+            )),
+            ModuleType::Mail if self.value.is_none() => Err(anyhow!(
+                "Mail block '{}' must have a value with an IMAP server",
+                self.name
+            )),
+            ModuleType::Mail if self.user.is_none() || self.password_env.is_none() => Err(anyhow!(
+                "Mail block '{}' must have a user and password_env",
+                self.name
+            )),
+            ModuleType::Weather
+                if self.provider == Some(WeatherProvider::OpenWeatherMap)
+                    && (self.value.is_none() || self.api_key_env.is_none()) =>
+            {
+                Err(anyhow!(
+                    "Weather block '{}' must have a value with a location, and an api_key_env, for OpenWeatherMap",
+                    self.name
+                ))
+            }
+            ModuleType::Countdown => match &self.value {
+                Some(value) => countdown_end(value, chrono::Local::now())
+                    .map(|_| ())
+                    .map_err(|e| anyhow!("Countdown block '{}': {}", self.name, e)),
+                None => Err(anyhow!(
+                    "Countdown block '{}' must have a value with a time or duration",
+                    self.name
+                )),
+            },
+            ModuleType::Vpn if self.value.is_none() => Err(anyhow!(
+                "VPN block '{}' must have a value with the interface of the tunnel",
+                self.name
+            )),
+            ModuleType::Memory => match &self.value {
+                Some(value) if value != MEMORY_SWAP => Err(anyhow!(
+                    "Memory block '{}' has value '{}', which must be {} or unset",
+                    self.name,
+                    value,
+                    MEMORY_SWAP
+                )),
+                _ => Ok(()),
+            },
+            ModuleType::CPU => match &self.value {
+                Some(value) if value.parse::<usize>().is_err() => Err(anyhow!(
+                    "CPU block '{}' has value '{}', which must be a core index",
+                    self.name,
+                    value
+                )),
+                _ => Ok(()),
+            },
+            ModuleType::Time => match self.value.as_deref() {
+                Some(value) if !value.is_empty() && value.parse::<chrono_tz::Tz>().is_err() => {
+                    Err(anyhow!(
+                        "Time block '{}' has value '{}', which is not a valid IANA timezone",
+                        self.name,
+                        value
+                    ))
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // expands environment variables in the string fields
+    fn expand_env(&mut self) -> Result<()> {
+        for field in [
+            &mut self.value,
+            &mut self.format,
+            &mut self.short_format,
+            &mut self.on_click,
+            &mut self.user,
+            &mut self.password_env,
+            &mut self.mailbox,
+            &mut self.url,
+            &mut self.api_key_env,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *field = expand_env(field)
+                .map_err(|e| anyhow!("Block '{}' could not be loaded: {}", self.name, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn abort_collector(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+
+    // the collector runs at its next launch, as if it had never run
+    pub fn force_collector(&mut self) {
+        self.last_updated = chrono::DateTime::default();
+    }
+
+    pub fn pango(&self) -> bool {
+        self.markup.as_deref() == Some("pango")
+    }
+
     pub async fn launch_collector(
         &mut self,
-        s: UnboundedSender<Collection>,
+        s: CollectionSender,
         result: UnboundedSender<Result<()>>,
         commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        state: ProtectedState,
+        snapshot: &Snapshot,
+        interval: chrono::Duration,
     ) -> Result<()> {
-        if (self.update_interval.is_some()
-            && self.last_updated + self.update_interval.clone().unwrap().duration()
-                < chrono::Local::now())
-            || self.update_interval.is_none()
+        let interval = self
+            .update_interval
+            .clone()
+            .map(|x| x.duration())
+            .or(self.typ.default_update_interval())
+            .unwrap_or(interval)
+            .max(self.typ.min_update_interval().unwrap_or_default())
+            .num_milliseconds()
+            .max(1);
+
+        // each consecutive failure doubles the interval, up to MAX_BACKOFF
+        let failures = self.failures.load(Ordering::Relaxed).min(16);
+        let interval = interval
+            .saturating_mul(1 << failures)
+            .min(interval.max(MAX_BACKOFF));
+
+        // collectors run once per wall clock multiple of their interval, so they stay in step
+        // with the frames emitted by the bar.
+        let now = chrono::Local::now();
+        if now.timestamp_millis().div_euclid(interval)
+            > self.last_updated.timestamp_millis().div_euclid(interval)
         {
+            self.validate()?;
+            // the collection carries when it was launched, so the bar can tell it from later ones
+            self.last_updated = now;
             let clone = self.clone();
+            let failures = self.failures.clone();
+            let snapshot = snapshot.clone();
+            let failed = BlockFailed {
+                name: self.name.clone(),
+                typ: self.typ.clone(),
+            };
+            log::debug!("Launching block '{}' ({})", self.name, self.typ.name());
 
-            match self.typ {
-                ModuleType::Static => {
-                    if self.value.is_some() {
-                        tokio::spawn(spawn(result, collect_static(s, clone)));
-                    } else {
-                        return Err(anyhow!(
-                            "Static block '{}' must have a value",
-                            self.clone().name
-                        ));
-                    }
+            let task = match self.typ {
+                ModuleType::Static => spawn(result, failures, failed, collect_static(s, clone)),
+                ModuleType::Dynamic => spawn(
+                    result,
+                    failures,
+                    failed,
+                    collect_dynamic(s, clone, commands.clone(), state),
+                ),
+                ModuleType::Time => spawn(result, failures, failed, collect_time(s, clone)),
+                ModuleType::Load => {
+                    spawn(result, failures, failed, collect_load(s, clone, snapshot))
+                }
+                ModuleType::CPU => spawn(
+                    result,
+                    failures,
+                    failed,
+                    collect_cpu(s, clone, state, snapshot),
+                ),
+                ModuleType::Memory => {
+                    spawn(result, failures, failed, collect_memory(s, clone, snapshot))
                 }
-                ModuleType::Dynamic => {
-                    tokio::spawn(spawn(result, collect_dynamic(s, clone, commands.clone())));
+                ModuleType::Disk => spawn(
+                    result,
+                    failures,
+                    failed,
+                    collect_disk(s, clone, state, snapshot),
+                ),
+                ModuleType::Music => spawn(result, failures, failed, collect_music(s, clone)),
+                ModuleType::Command => spawn(result, failures, failed, collect_command(s, clone)),
+                ModuleType::Battery => spawn(result, failures, failed, collect_battery(s, clone)),
+                ModuleType::Network => {
+                    spawn(result, failures, failed, collect_network(s, clone, state))
                 }
-                ModuleType::Time => {
-                    tokio::spawn(spawn(result, collect_time(s, clone)));
+                ModuleType::Temperature => {
+                    spawn(result, failures, failed, collect_temperature(s, clone))
                 }
-                ModuleType::Load => {
-                    tokio::spawn(spawn(result, collect_load(s, clone)));
+                ModuleType::Volume => spawn(result, failures, failed, collect_volume(s, clone)),
+                ModuleType::Brightness => {
+                    spawn(result, failures, failed, collect_brightness(s, clone))
                 }
-                ModuleType::CPU => {
-                    tokio::spawn(spawn(result, collect_cpu(s, clone)));
+                ModuleType::Wifi => spawn(result, failures, failed, collect_wifi(s, clone)),
+                ModuleType::Uptime => spawn(result, failures, failed, collect_uptime(s, clone)),
+                ModuleType::Status => {
+                    spawn(result, failures, failed, collect_status(s, clone, state))
                 }
-                ModuleType::Memory => {
-                    tokio::spawn(spawn(result, collect_memory(s, clone)));
+                ModuleType::PublicIp => {
+                    spawn(result, failures, failed, collect_public_ip(s, clone, state))
                 }
-                ModuleType::Disk => {
-                    tokio::spawn(spawn(result, collect_disk(s, clone)));
+                ModuleType::Mail => spawn(result, failures, failed, collect_mail(s, clone, state)),
+                ModuleType::Notifications => {
+                    spawn(result, failures, failed, collect_notifications(s, clone))
                 }
-                ModuleType::Music => {
-                    tokio::spawn(spawn(result, collect_music(s, clone)));
+                ModuleType::Bluetooth => {
+                    spawn(result, failures, failed, collect_bluetooth(s, clone))
                 }
-                ModuleType::Command => {
-                    tokio::spawn(spawn(result, collect_command(s, clone)));
+                ModuleType::Keyboard => spawn(result, failures, failed, collect_keyboard(s, clone)),
+                ModuleType::Processes => {
+                    spawn(result, failures, failed, collect_processes(s, clone))
                 }
-            }
+                ModuleType::Gpu => spawn(result, failures, failed, collect_gpu(s, clone)),
+                ModuleType::Fan => spawn(result, failures, failed, collect_fan(s, clone)),
+                ModuleType::Vpn => spawn(result, failures, failed, collect_vpn(s, clone)),
+                ModuleType::Weather => {
+                    spawn(result, failures, failed, collect_weather(s, clone, state))
+                }
+                ModuleType::DiskIo => {
+                    spawn(result, failures, failed, collect_disk_io(s, clone, state))
+                }
+                ModuleType::Countdown => {
+                    spawn(result, failures, failed, collect_countdown(s, clone, state))
+                }
+            };
 
-            self.last_updated = chrono::Local::now();
+            self.tasks.retain(|x| !x.is_finished());
+            self.tasks.push(task);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn toml_pages() {
+        let config: Config = toml::from_str(
+            r#"
+            placeholders = "braces"
+
+            [[pages]]
+            update_interval = "500ms"
+
+            [[pages.items]]
+            name = "cpu"
+            type = "cpu"
+
+            [[pages.items]]
+            name = "hello"
+            type = "static"
+            value = "hello"
+            "#,
+        )
+        .unwrap();
+        let pages = config.pages();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            pages[0].update_interval(),
+            Some(chrono::Duration::milliseconds(500))
+        );
+        let items = pages[0].items();
+        assert_eq!(items[0].typ, ModuleType::CPU);
+        assert_eq!(items[1].value.as_deref(), Some("hello"));
+        assert_eq!(config.placeholders, Some(Placeholders::Braces));
+    }
+
+    #[test]
+    fn toml_pages_of_inline_items() {
+        let config: Config =
+            toml::from_str(r#"pages = [[{ name = "s", type = "static" }]]"#).unwrap();
+        assert_eq!(config.pages()[0].items()[0].name, "s");
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn config_paths_with_xdg() {
+        assert_eq!(
+            config_paths(Some("/xdg".into()), Some("/home/me".into())),
+            paths(&[
+                "/xdg/yaib/config.yaml",
+                "/home/me/.config/yaib/config.yaml",
+                "/xdg/yaib/yaib.config.yaml",
+                "/home/me/.config/yaib/yaib.config.yaml",
+                "/xdg/yaib/yaib.config.toml",
+                "/home/me/.config/yaib/yaib.config.toml",
+            ])
+        );
+        // an XDG_CONFIG_HOME of ~/.config is only searched once
+        assert_eq!(
+            config_paths(Some("/home/me/.config".into()), Some("/home/me".into())),
+            paths(&[
+                "/home/me/.config/yaib/config.yaml",
+                "/home/me/.config/yaib/yaib.config.yaml",
+                "/home/me/.config/yaib/yaib.config.toml",
+            ])
+        );
+    }
+
+    #[test]
+    fn config_paths_without_xdg() {
+        let home = paths(&[
+            "/home/me/.config/yaib/config.yaml",
+            "/home/me/.config/yaib/yaib.config.yaml",
+            "/home/me/.config/yaib/yaib.config.toml",
+        ]);
+        assert_eq!(config_paths(None, Some("/home/me".into())), home);
+        // a relative XDG_CONFIG_HOME is ignored
+        assert_eq!(
+            config_paths(Some("xdg".into()), Some("/home/me".into())),
+            home
+        );
+        assert!(config_paths(None, None).is_empty());
+    }
+
+    #[test]
+    fn find_config_falls_back_to_home() {
+        let tried = config_paths(Some("/xdg".into()), Some("/home/me".into()));
+        let found = find_config(&tried, |x| x.starts_with("/home/me")).unwrap();
+        assert_eq!(found, PathBuf::from("/home/me/.config/yaib/config.yaml"));
+        // config.yaml wins in either directory, and then XDG_CONFIG_HOME wins for each older name
+        let found = find_config(&tried, |x| {
+            x.starts_with("/home/me") || x.ends_with("yaib.config.toml")
+        })
+        .unwrap();
+        assert_eq!(found, PathBuf::from("/home/me/.config/yaib/config.yaml"));
+        let found = find_config(&tried, |x| x.ends_with("yaib.config.yaml")).unwrap();
+        assert_eq!(found, PathBuf::from("/xdg/yaib/yaib.config.yaml"));
+    }
+
+    #[test]
+    fn find_config_lists_every_path() {
+        let tried = config_paths(Some("/xdg".into()), Some("/home/me".into()));
+        let err = find_config(&tried, |_| false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No configuration file was found; tried:
+    /xdg/yaib/config.yaml
+    /home/me/.config/yaib/config.yaml
+    /xdg/yaib/yaib.config.yaml
+    /home/me/.config/yaib/yaib.config.yaml
+    /xdg/yaib/yaib.config.toml
+    /home/me/.config/yaib/yaib.config.toml
+Pass one with --config, or set YAIB_CONFIG."
+        );
+    }
+}
@@ -1,22 +1,456 @@
+use crate::config::{Align, Placeholders, Units};
+
+// placeholders are named like %name, whichever way the format writes them
 pub type Rules<'a> = Vec<(&'a str, String)>;
 
 pub struct Format<'a> {
     format: String,
     rules: Rules<'a>,
+    numbered: Vec<(&'a str, Vec<String>)>,
+    markup: bool,
+    placeholders: Placeholders,
 }
 
 impl<'a> Format<'a> {
     pub fn new(format: String, rules: Rules<'a>) -> Self {
-        Self { format, rules }
+        Self {
+            format,
+            rules,
+            numbered: Vec::new(),
+            markup: false,
+            placeholders: Placeholders::default(),
+        }
+    }
+
+    // when the format is pango markup, substituted values are escaped so they cannot break it
+    pub fn markup(mut self, markup: bool) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    // a placeholder for each of the values, numbered from 0, like %core0 and %core1
+    pub fn numbered(mut self, name: &'a str, values: Vec<String>) -> Self {
+        self.numbered.push((name, values));
+        self
     }
 
+    // whether the format writes placeholders as %name or {name}
+    pub fn placeholders(mut self, placeholders: Placeholders) -> Self {
+        self.placeholders = placeholders;
+        self
+    }
+
+    // Substitutes the placeholders in one pass, so values are never substituted themselves. The
+    // longest placeholder wins, so %1 does not clobber %15, and a doubled delimiter, like %% or
+    // {{, is the delimiter itself.
     pub fn format(&self) -> String {
-        let mut res = self.format.clone();
+        // text without placeholders, like a static value or the output of a command, is final
+        if self.rules.is_empty() && self.numbered.is_empty() {
+            return self.format.clone();
+        }
 
-        for rule in &self.rules {
-            res = res.replace(rule.0, &rule.1);
+        self.substitute(&self.format, &self.named_values())
+    }
+
+    // every placeholder, as the format would write it, e.g. %pct or {pct}, and its value
+    pub fn values(&self) -> Vec<(String, String)> {
+        let (open, close) = self.delimiters();
+        self.named_values()
+            .into_iter()
+            .map(|(name, value)| (format!("{}{}{}", open, name, close), value.clone()))
+            .collect()
+    }
+
+    // the names of the placeholders, without their delimiters, and their values
+    fn named_values(&self) -> Vec<(String, &String)> {
+        self.rules
+            .iter()
+            .map(|(name, value)| (name.trim_start_matches('%').to_string(), value))
+            .chain(self.numbered.iter().flat_map(|(name, values)| {
+                values.iter().enumerate().map(move |(i, value)| {
+                    (format!("{}{}", name.trim_start_matches('%'), i), value)
+                })
+            }))
+            .collect()
+    }
+
+    fn delimiters(&self) -> (&'static str, &'static str) {
+        match self.placeholders {
+            Placeholders::Percent => ("%", ""),
+            Placeholders::Braces => ("{", "}"),
+        }
+    }
+
+    fn substitute(&self, text: &str, values: &[(String, &String)]) -> String {
+        let (open, close) = self.delimiters();
+
+        let mut res = String::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if let Some(delimiter) = [open, close]
+                .into_iter()
+                .find(|x| !x.is_empty() && rest.starts_with(&x.repeat(2)))
+            {
+                res.push_str(delimiter);
+                rest = &rest[delimiter.len() * 2..];
+                continue;
+            }
+
+            if let Some((branch, len)) = conditional(rest, values) {
+                res.push_str(&self.substitute(branch, values));
+                rest = &rest[len..];
+                continue;
+            }
+
+            if let Some((len, value)) = values
+                .iter()
+                .filter_map(|(name, value)| {
+                    let placeholder = format!("{}{}{}", open, name, close);
+                    rest.starts_with(&placeholder)
+                        .then_some((placeholder.len(), value))
+                })
+                .max_by_key(|(len, _)| *len)
+            {
+                if self.markup {
+                    res.push_str(&escape_markup(value));
+                } else {
+                    res.push_str(value);
+                }
+                rest = &rest[len..];
+                continue;
+            }
+
+            res.push(c);
+            rest = &rest[c.len_utf8()..];
         }
 
         res
     }
 }
+
+// A conditional at the start of the text, like {if pct>90}high{else}ok{endif}, as the branch its
+// comparison chooses and the length of the whole conditional. Conditionals may be nested, and
+// the {else} is optional. Malformed ones, or ones comparing an unknown name, are left as text.
+fn conditional<'b>(text: &'b str, values: &[(String, &String)]) -> Option<(&'b str, usize)> {
+    let condition = text.strip_prefix("{if ")?;
+    let condition = &condition[..condition.find('}')?];
+    let holds = compare(condition, values)?;
+
+    let start = "{if }".len() + condition.len();
+    let body = &text[start..];
+    let mut depth = 0;
+    let mut otherwise = None;
+    for (i, _) in body.char_indices() {
+        let tail = &body[i..];
+        if tail.starts_with("{if ") {
+            depth += 1;
+        } else if tail.starts_with("{else}") && depth == 0 {
+            otherwise.get_or_insert(i);
+        } else if tail.starts_with("{endif}") {
+            if depth > 0 {
+                depth -= 1;
+                continue;
+            }
+
+            let (then, otherwise) = match otherwise {
+                Some(at) => (&body[..at], &body[at + "{else}".len()..i]),
+                None => (&body[..i], ""),
+            };
+            return Some((
+                if holds { then } else { otherwise },
+                start + i + "{endif}".len(),
+            ));
+        }
+    }
+
+    None
+}
+
+// One comparison of a placeholder's value, like pct>90 or status==charging. Numbers compare
+// by value; anything else can only be equal or not.
+fn compare(condition: &str, values: &[(String, &String)]) -> Option<bool> {
+    let at = condition.find(['<', '>', '=', '!'])?;
+    let op = ["<=", ">=", "==", "!=", "<", ">"]
+        .into_iter()
+        .find(|op| condition[at..].starts_with(op))?;
+    let name = condition[..at].trim().trim_start_matches('%');
+    let right = condition[at + op.len()..].trim();
+    let left = values.iter().find(|(x, _)| x == name)?.1.trim();
+
+    Some(match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(left), Ok(right)) => match op {
+            "<=" => left <= right,
+            ">=" => left >= right,
+            "==" => left == right,
+            "!=" => left != right,
+            "<" => left < right,
+            _ => left > right,
+        },
+        _ => match op {
+            "==" => left == right,
+            "!=" => left != right,
+            _ => false,
+        },
+    })
+}
+
+pub fn escape_markup(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub const DEFAULT_BAR_WIDTH: usize = 10;
+pub const DEFAULT_BAR_FULL: &str = "█";
+pub const DEFAULT_BAR_EMPTY: &str = "░";
+
+// renders a percentage as a fixed-width bar of full and empty glyphs
+pub fn bar(pct: f64, width: usize, full: &str, empty: &str) -> String {
+    let filled = if pct.is_nan() {
+        0
+    } else {
+        ((pct.clamp(0.0, 100.0) / 100.0) * width as f64).round() as usize
+    };
+
+    full.repeat(filled) + &empty.repeat(width - filled)
+}
+
+// Whether the character belongs to the grapheme before it: combining marks, variation
+// selectors, emoji skin tones and zero width joiners. This covers what text in a bar needs,
+// rather than every rule of UAX #29.
+fn extends_grapheme(c: char) -> bool {
+    matches!(c as u32,
+        0x0300..=0x036F
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x20D0..=0x20FF
+            | 0xFE20..=0xFE2F
+            | 0xFE00..=0xFE0F
+            | 0xE0100..=0xE01EF
+            | 0x1F3FB..=0x1F3FF
+            | 0x200D)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+// splits the text into the characters a reader sees, e.g. an "e" with its accent, or a flag
+pub fn graphemes(s: &str) -> Vec<&str> {
+    let mut v = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    let mut indicators = 0;
+
+    for (i, c) in s.char_indices() {
+        let joined = prev == Some('\u{200D}');
+        // regional indicators pair up into flags
+        let flag = is_regional_indicator(c) && indicators % 2 == 1;
+
+        if i > 0 && !extends_grapheme(c) && !joined && !flag {
+            v.push(&s[start..i]);
+            start = i;
+        }
+
+        indicators = if is_regional_indicator(c) {
+            indicators + 1
+        } else {
+            0
+        };
+        prev = Some(c);
+    }
+
+    if start < s.len() {
+        v.push(&s[start..]);
+    }
+
+    v
+}
+
+// shortens the text to at most the length in graphemes, ending it with an ellipsis when cut
+pub fn truncate(s: &str, len: usize) -> String {
+    let graphemes = graphemes(s);
+
+    if graphemes.len() <= len {
+        s.to_string()
+    } else if len == 0 {
+        String::new()
+    } else {
+        graphemes[..len - 1].concat() + "…"
+    }
+}
+
+pub const DEFAULT_SCROLL_WIDTH: usize = 20;
+const SCROLL_GAP: &str = "   ";
+
+// a window of the width over the text, starting at the offset and wrapping around with a gap;
+// text which fits is left as it is.
+pub fn scroll(s: &str, width: usize, offset: usize) -> String {
+    let text = graphemes(s);
+    if text.len() <= width {
+        return s.to_string();
+    }
+
+    let looped = [text, graphemes(SCROLL_GAP)].concat();
+    (0..width)
+        .map(|i| looped[(offset + i) % looped.len()])
+        .collect()
+}
+
+// pads the text with spaces to at least the width in graphemes, placing it by the alignment
+pub fn pad(s: &str, width: usize, align: Align) -> String {
+    let padding = width.saturating_sub(graphemes(s).len());
+
+    match align {
+        Align::Left => format!("{}{}", s, " ".repeat(padding)),
+        Align::Right => format!("{}{}", " ".repeat(padding), s),
+        Align::Center => format!(
+            "{}{}{}",
+            " ".repeat(padding / 2),
+            s,
+            " ".repeat(padding - padding / 2)
+        ),
+    }
+}
+
+pub const DEFAULT_HISTORY: usize = 10;
+const SPARKLINE: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// renders samples as a line of glyphs, scaled from the least of them to the greatest
+pub fn sparkline(samples: &[f64]) -> String {
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    samples
+        .iter()
+        .map(|x| {
+            // flat histories are drawn at the bottom
+            let level = if max > min {
+                ((x - min) / (max - min) * (SPARKLINE.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+
+            SPARKLINE[level.min(SPARKLINE.len() - 1)]
+        })
+        .collect()
+}
+
+const DECIMAL_UNITS: &[&str] = &["B", "kB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB"];
+const BINARY_UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB", "ZiB", "YiB"];
+
+// a unit of bytes by its name, e.g. MiB, in any case, as its units and power of them
+pub fn byte_unit(name: &str) -> Option<(Units, usize)> {
+    [
+        (Units::Decimal, DECIMAL_UNITS),
+        (Units::Binary, BINARY_UNITS),
+    ]
+    .into_iter()
+    .find_map(|(units, names)| {
+        names
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(name))
+            .map(|exponent| (units, exponent))
+    })
+}
+
+// Renders a number of bytes, e.g. 1.5 GB, or 1.4 GiB in binary units. The unit may be pinned,
+// e.g. to show 1536 MB, and the number of decimals fixed; otherwise the largest unit under the
+// number is used, with two decimals at most.
+pub fn bytes(n: f64, units: Units, unit: Option<&str>, precision: Option<u8>) -> String {
+    let pinned = unit.and_then(byte_unit);
+    if pinned.is_none() && precision.is_none() && units == Units::Decimal {
+        return pretty_bytes::converter::convert(n);
+    }
+
+    let (units, exponent) = pinned.unwrap_or_else(|| {
+        let base = if units == Units::Binary {
+            1024_f64
+        } else {
+            1000_f64
+        };
+        let exponent = if n.abs() < 1.0 {
+            0
+        } else {
+            ((n.abs().ln() / base.ln()).floor() as usize).min(BINARY_UNITS.len() - 1)
+        };
+        (units, exponent)
+    });
+    let (base, names) = match units {
+        Units::Decimal => (1000_f64, DECIMAL_UNITS),
+        Units::Binary => (1024_f64, BINARY_UNITS),
+    };
+
+    let value = n / base.powi(exponent as i32);
+    let value = match precision {
+        Some(precision) => format!("{:.*}", precision as usize, value),
+        // without trailing zeroes, as pretty_bytes does
+        None => format!("{:.2}", value)
+            .parse::<f64>()
+            .unwrap_or_default()
+            .to_string(),
+    };
+
+    format!("{} {}", value, names[exponent])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the placeholders of memory blocks, several of which start with another's name
+    fn memory(format: &str) -> String {
+        Format::new(
+            format.to_string(),
+            vec![
+                ("%total", "16 GB".to_string()),
+                ("%usage", "8 GB".to_string()),
+                ("%pct", "50.0".to_string()),
+                ("%swap_total", "4 GB".to_string()),
+                ("%swap_usage", "1 GB".to_string()),
+                ("%pct_swap", "25.0".to_string()),
+            ],
+        )
+        .format()
+    }
+
+    #[test]
+    fn totals_get_their_own_values() {
+        assert_eq!(memory("%total %swap_total"), "16 GB 4 GB");
+        assert_eq!(memory("%swap_total %total"), "4 GB 16 GB");
+        assert_eq!(memory("%total%swap_total"), "16 GB4 GB");
+        assert_eq!(memory("%swap_total%total"), "4 GB16 GB");
+    }
+
+    #[test]
+    fn percentages_get_their_own_values() {
+        assert_eq!(memory("%pct %pct_swap"), "50.0 25.0");
+        assert_eq!(memory("%pct_swap %pct"), "25.0 50.0");
+        assert_eq!(memory("%pct%pct_swap"), "50.025.0");
+        assert_eq!(memory("%pct_swap%pct"), "25.050.0");
+    }
+
+    #[test]
+    fn no_partial_replacement() {
+        // the longer placeholder wins, so %pct never eats the start of %pct_swap
+        let text = memory("%pct_swap|%swap_total|%swap_usage");
+        assert_eq!(text, "25.0|4 GB|1 GB");
+        assert!(!text.contains("50.0"));
+        assert!(!text.contains('%'));
+    }
+
+    #[test]
+    fn escape_next_to_a_placeholder() {
+        // the default volume format, where a literal % is followed by %mute
+        let format = crate::config::ModuleType::Volume.default_format().unwrap();
+        let text = Format::new(
+            format.to_string(),
+            vec![
+                ("%percent", "50".to_string()),
+                ("%mute", " (muted)".to_string()),
+            ],
+        )
+        .format();
+        assert_eq!(text, "vol: 50% (muted)");
+    }
+}
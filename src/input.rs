@@ -1,13 +1,36 @@
 use crate::{
-    bar::{Click, NAME_PAGE_DOWN, NAME_PAGE_UP},
+    bar::{Click, NAME_PAGE_DOWN, NAME_PAGE_UP, NAME_SPACER},
+    collectors::command_parts,
     config::Config,
     state::ProtectedState,
 };
-use tokio::io::AsyncReadExt;
+use anyhow::{anyhow, Result};
+use tokio::{io::AsyncReadExt, sync::watch};
 
-pub async fn manage_clicks(state: ProtectedState, config: Config) {
+fn run_on_click(command: &str, click: &Click) -> Result<()> {
+    let parts = command_parts(command);
+    if parts.is_empty() {
+        return Err(anyhow!("on_click for block '{}' is empty", click.name));
+    }
+
+    let mut child = tokio::process::Command::new(&parts[0])
+        .args(&parts[1..])
+        .env("YAIB_BUTTON", click.button.to_string())
+        .env("YAIB_NAME", &click.name)
+        .spawn()?;
+
+    tokio::spawn(async move { child.wait().await });
+    Ok(())
+}
+
+pub async fn manage_clicks(state: ProtectedState, configs: watch::Receiver<Config>) {
     let mut v = Vec::with_capacity(4096);
-    while let Ok(_) = tokio::io::stdin().read_buf(&mut v).await {
+    while let Ok(n) = tokio::io::stdin().read_buf(&mut v).await {
+        if n == 0 {
+            // stdin was closed; no more clicks are coming.
+            break;
+        }
+
         let mut lock = state.lock().await;
 
         if v.len() > 2 && v[0] as char == '[' && v[1] as char == '\n' {
@@ -25,6 +48,7 @@ pub async fn manage_clicks(state: ProtectedState, config: Config) {
         }
 
         if let Ok(click) = serde_json::from_slice::<Click>(&v) {
+            let config = configs.borrow().clone();
             match click.name.as_str() {
                 NAME_PAGE_UP => {
                     if lock.page < config.pages().len() - 1 {
@@ -36,8 +60,13 @@ pub async fn manage_clicks(state: ProtectedState, config: Config) {
                         lock.page -= 1
                     }
                 }
-                _ => {
-                    if lock.opened.contains(&click.name) {
+                NAME_SPACER => {}
+                name => {
+                    if let Some(on_click) = config.item(name).and_then(|item| item.on_click) {
+                        if let Err(e) = run_on_click(&on_click, &click) {
+                            log::error!("Could not run on_click of block '{}': {:#}", name, e);
+                        }
+                    } else if lock.opened.contains(&click.name) {
                         let mut v = Vec::new();
                         for i in &lock.opened {
                             if *i != click.name {
@@ -51,7 +80,8 @@ pub async fn manage_clicks(state: ProtectedState, config: Config) {
                     }
                 }
             }
-            v = Vec::new();
         }
+
+        v = Vec::new();
     }
 }
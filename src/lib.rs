@@ -1,7 +1,9 @@
 pub mod bar;
+pub mod channel;
 pub mod collectors;
 pub mod config;
 pub mod formatter;
 pub mod input;
+pub mod metrics;
 pub mod state;
 pub mod unix;
@@ -0,0 +1,84 @@
+use crate::state::ProtectedState;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+// label values escape backslashes, quotes and newlines
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// the latest metrics of every block, in the Prometheus text format
+async fn exposition(state: ProtectedState) -> String {
+    // blocks are grouped under each metric, as the format requires
+    let mut metrics: BTreeMap<&str, Vec<(String, f64)>> = BTreeMap::new();
+    for (name, values) in &state.lock().await.metrics {
+        for (metric, value) in values {
+            metrics
+                .entry(metric)
+                .or_default()
+                .push((name.clone(), *value));
+        }
+    }
+
+    let mut s = String::new();
+    for (metric, values) in metrics {
+        s += &format!("# TYPE yaib_{} gauge\n", metric);
+        for (name, value) in values {
+            s += &format!(
+                "yaib_{}{{module=\"{}\"}} {}\n",
+                metric,
+                escape_label(&name),
+                value
+            );
+        }
+    }
+
+    s
+}
+
+// Serves the metrics over HTTP on the port, on localhost only. Every request is answered with
+// them, whatever its path.
+pub async fn manage_metrics(port: u16, state: ProtectedState) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            // the request is read up to the end of its headers, and otherwise ignored
+            let mut v = Vec::new();
+            let mut buf = [0; 1024];
+            while !v.windows(4).any(|x| x == b"\r\n\r\n") && v.len() < 65536 {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => v.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let body = exposition(state).await;
+            let _ = stream
+                .write_all(
+                    format!(
+                        concat!(
+                            "HTTP/1.1 200 OK\r\n",
+                            "Content-Type: text/plain; version=0.0.4\r\n",
+                            "Content-Length: {}\r\n",
+                            "Connection: close\r\n\r\n{}"
+                        ),
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await;
+        });
+    }
+
+    Ok(())
+}
@@ -1,11 +1,78 @@
 #![allow(dead_code)]
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
 use tokio::sync::Mutex;
 
 #[derive(Debug, Clone, Default)]
 pub struct State {
     pub page: usize,
     pub opened: Vec<String>,
+    pub network: BTreeMap<String, NetworkSample>,
+    pub disk_io: BTreeMap<String, DiskIoSample>,
+    // the volumes last read by any disk block, and when; locked while they are read again
+    pub volumes: Arc<Mutex<Option<VolumeSample>>>,
+    pub cpu: BTreeMap<String, CpuSample>,
+    pub public_ip: BTreeMap<String, PublicIpSample>,
+    pub weather: BTreeMap<String, WeatherSample>,
+    // the value each countdown was started with, and when it ends
+    pub countdown: BTreeMap<String, (String, chrono::DateTime<chrono::Local>)>,
+    pub mail: BTreeMap<String, Arc<Mutex<Option<crate::collectors::MailSession>>>>,
+    // the latest block written to the unix socket under each name
+    pub dynamic: BTreeMap<String, crate::config::CommandItem>,
+    // the last samples of each block, oldest first, for sparklines
+    pub history: BTreeMap<String, VecDeque<f64>>,
+    // the values each block with smooth set last showed
+    pub smoothed: BTreeMap<String, Vec<f64>>,
+    // the text each scrolling block last showed, and how far it has scrolled
+    pub scroll: BTreeMap<String, (String, usize)>,
+    // the error of each block whose last collection failed, shown with error_display: block
+    pub errors: BTreeMap<String, String>,
+    // how many collectors were running when they were last launched
+    pub collectors: usize,
+    // when the bar last wrote a frame
+    pub last_frame: Option<chrono::DateTime<chrono::Local>>,
+    // the latest metrics of each block, as served to Prometheus
+    pub metrics: BTreeMap<String, Vec<(&'static str, f64)>>,
 }
 
+#[derive(Debug, Clone)]
+pub struct NetworkSample {
+    pub rx: u64,
+    pub tx: u64,
+    pub time: chrono::DateTime<chrono::Local>,
+}
+
+// bytes read and written since boot
+#[derive(Debug, Clone)]
+pub struct DiskIoSample {
+    pub read: u64,
+    pub written: u64,
+    pub time: chrono::DateTime<chrono::Local>,
+}
+
+pub type VolumeSample = (tokio::time::Instant, Vec<mprober_lib::volume::Volume>);
+
 pub type ProtectedState = Arc<Mutex<State>>;
+
+// idle and total jiffies for each core, as read from /proc/stat
+#[derive(Debug, Clone, Default)]
+pub struct CpuSample {
+    pub cores: Vec<(u64, u64)>,
+}
+
+// the last public addresses found, shown when a lookup fails
+#[derive(Debug, Clone, Default)]
+pub struct PublicIpSample {
+    pub v4: Option<String>,
+    pub v6: Option<String>,
+}
+
+// the last weather fetched, shown when a lookup fails
+#[derive(Debug, Clone, Default)]
+pub struct WeatherSample {
+    pub temp: f64,
+    pub condition: String,
+    pub location: String,
+}
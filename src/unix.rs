@@ -1,21 +1,54 @@
 use crate::config::CommandItem;
 use anyhow::Result;
-use tokio::{io::AsyncReadExt, net::UnixListener, sync::mpsc::UnboundedSender};
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::UnixListener,
+    sync::{mpsc::UnboundedSender, Notify},
+};
 
 pub const SOCKET_PATH: &str = "/tmp/yaib.sock";
 
-pub async fn manage_unix_socket(blocks: UnboundedSender<CommandItem>) -> Result<()> {
-    let _ = std::fs::remove_file(SOCKET_PATH);
-    let listener = UnixListener::bind(SOCKET_PATH)?;
+// Each line written to the socket is a block, which wakes `written`. Clients are served at the
+// same time, and may stay connected to write as many blocks as they like.
+pub async fn manage_unix_socket(
+    path: PathBuf,
+    blocks: UnboundedSender<CommandItem>,
+    written: Arc<Notify>,
+) -> Result<()> {
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
 
-    while let Ok((mut stream, _)) = listener.accept().await {
-        let mut v = Vec::new();
-        stream.read_to_end(&mut v).await?;
-        drop(stream);
-        if let Ok(block) = serde_json::from_slice(&v) {
-            blocks.send(block)?;
-        }
+    while let Ok((stream, _)) = listener.accept().await {
+        let blocks = blocks.clone();
+        let written = written.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stream).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if let Some(block) = parse_block(&line) {
+                    if blocks.send(block).is_err() {
+                        break;
+                    }
+                    written.notify_one();
+                }
+            }
+        });
     }
 
     Ok(())
 }
+
+// a block is JSON, like the output of a command, or name=value; anything else is ignored
+fn parse_block(line: &str) -> Option<CommandItem> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return serde_json::from_str(line).ok();
+    }
+
+    let (name, value) = line.split_once('=')?;
+    Some(CommandItem {
+        name: name.trim().to_string(),
+        value: value.trim().to_string(),
+        ..Default::default()
+    })
+}